@@ -1,7 +1,7 @@
 use std::io::{Read, Write};
 use std::ops::ControlFlow;
 
-use crate::runner::{StopRunner, ControlExecuteMessage};
+use crate::runner::{StopRunner, ControlExecuteMessage, DeadlineExceeded};
 #[derive(Debug)]
 pub struct AFile(std::fs::File);
 
@@ -29,6 +29,12 @@ pub enum ActionResult {
     Close,
 }
 
+impl From<DeadlineExceeded> for std::io::Error {
+    fn from(e: DeadlineExceeded) -> Self {
+        std::io::Error::new(std::io::ErrorKind::TimedOut, e)
+    }
+}
+
 impl ActionRequest {
     fn exec(self) -> Result<ActionResult, std::io::Error> {
         match self {
@@ -56,5 +62,14 @@ impl ControlExecuteMessage for ActionRequest {
             o => ControlFlow::Continue(o.exec()),
         }
     }
+    /// KiB to read or write, at least 1
+    fn cost(&self) -> u64 {
+        let bytes = match self {
+            Self::Read(file) => file.0.metadata().map_or(0, |m| m.len()),
+            Self::WriteAll(_, buf) => buf.len() as u64,
+            _ => 0,
+        };
+        (bytes / 1024).max(1)
+    }
 }
 
@@ -0,0 +1,349 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::priority::Priority;
+use crate::runner::{ControlExecuteMessage, Ret, StopRunner};
+
+/// Returned instead of sending a request while it's [`CircuitBreaker`] is open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitOpen;
+impl Display for CircuitOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Circuit open, request rejected")
+    }
+}
+impl std::error::Error for CircuitOpen {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are let through and their results tracked
+    Closed,
+    /// Requests are rejected until the open period ends
+    Open,
+    /// A few probe requests are let through to decide whether to close again
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: CircuitState,
+    /// Whether each of the last results was a failure, while closed
+    window: VecDeque<bool>,
+    opened_at: Instant,
+    probes: usize,
+    probe_successes: usize,
+}
+
+/// Rejects requests while the results of the last ones fail too often
+///
+/// Opens once at least `failure_rate` of the last `window` results are failures, stays open for
+/// `open_for`, then lets `probes` requests through and closes again if all of them succeed
+pub struct CircuitBreaker<Res> {
+    is_failure: Box<dyn Fn(&Res) -> bool + Send + Sync>,
+    window: usize,
+    failure_rate: f64,
+    open_for: Duration,
+    probes: usize,
+    state: Mutex<BreakerState>,
+}
+
+impl<Res> std::fmt::Debug for CircuitBreaker<Res> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("window", &self.window)
+            .field("failure_rate", &self.failure_rate)
+            .field("open_for", &self.open_for)
+            .field("probes", &self.probes)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Res> CircuitBreaker<Res> {
+    /// Make a breaker that counts the results `is_failure` holds for as failures
+    pub fn new<F>(is_failure: F) -> Self
+    where
+        F: Fn(&Res) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            is_failure: Box::new(is_failure),
+            window: 20,
+            failure_rate: 0.5,
+            open_for: Duration::from_secs(5),
+            probes: 1,
+            state: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                window: VecDeque::new(),
+                opened_at: Instant::now(),
+                probes: 0,
+                probe_successes: 0,
+            }),
+        }
+    }
+    /// Open once at least `failure_rate` of the last `window` results are failures
+    ///
+    /// # Panics
+    ///
+    /// If `failure_rate` isn't in `(0, 1]`
+    pub fn threshold(mut self, failure_rate: f64, window: usize) -> Self {
+        assert!(
+            failure_rate > 0.0 && failure_rate <= 1.0,
+            "failure rate must be in (0, 1], got {failure_rate}"
+        );
+        self.failure_rate = failure_rate;
+        self.window = window.max(1);
+        self
+    }
+    pub fn open_for(mut self, open_for: Duration) -> Self {
+        self.open_for = open_for;
+        self
+    }
+    pub fn probes(mut self, probes: usize) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        state.state
+    }
+
+    fn expire(&self, state: &mut BreakerState) {
+        if state.state == CircuitState::Open && state.opened_at.elapsed() >= self.open_for {
+            state.state = CircuitState::HalfOpen;
+            state.probes = 0;
+            state.probe_successes = 0;
+        }
+    }
+
+    fn open(state: &mut BreakerState) {
+        state.state = CircuitState::Open;
+        state.opened_at = Instant::now();
+        state.window.clear();
+    }
+
+    fn admit(&self) -> Result<(), CircuitOpen> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        match state.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::HalfOpen if state.probes < self.probes => {
+                state.probes += 1;
+                Ok(())
+            }
+            CircuitState::HalfOpen | CircuitState::Open => Err(CircuitOpen),
+        }
+    }
+
+    fn record(&self, res: &Res) {
+        self.record_outcome((self.is_failure)(res));
+    }
+
+    fn record_outcome(&self, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        match state.state {
+            CircuitState::Closed => {
+                state.window.push_back(failed);
+                if state.window.len() > self.window {
+                    state.window.pop_front();
+                }
+                let failures = state.window.iter().filter(|failed| **failed).count();
+                if state.window.len() >= self.window
+                    && failures as f64 >= self.failure_rate * self.window as f64
+                {
+                    Self::open(&mut state);
+                }
+            }
+            CircuitState::HalfOpen if failed => Self::open(&mut state),
+            CircuitState::HalfOpen => {
+                state.probe_successes += 1;
+                if state.probe_successes >= self.probes {
+                    state.state = CircuitState::Closed;
+                }
+            }
+            // Finished after the circuit opened again
+            CircuitState::Open => {}
+        }
+    }
+
+    /// Give back a probe that was admitted but never executed
+    fn abandon(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.state == CircuitState::HalfOpen {
+            state.probes = state.probes.saturating_sub(1);
+        }
+    }
+}
+
+/// Counts a request panicking out of [`Guarded::execute`] as a failure, unless disarmed
+struct Unwinding<'a, Res>(Option<&'a CircuitBreaker<Res>>);
+
+impl<Res> Drop for Unwinding<'_, Res> {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.0 {
+            breaker.record_outcome(true);
+        }
+    }
+}
+
+/// Request admitted by a [`CircuitBreaker`], it's result is recorded by the breaker
+///
+/// A request that panics counts as a failure
+pub struct Guarded<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    /// `None` once executed
+    req: Option<Req>,
+    breaker: Option<Arc<CircuitBreaker<Ret<Req>>>>,
+}
+
+impl<Req> std::fmt::Debug for Guarded<Req>
+where
+    Req: ControlExecuteMessage + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Guarded")
+            .field("req", &self.req)
+            .field("breaker", &self.breaker)
+            .finish()
+    }
+}
+
+impl<Req> Guarded<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    /// Wrap `req` if `breaker` admits it
+    pub fn new(req: Req, breaker: &Arc<CircuitBreaker<Ret<Req>>>) -> Result<Self, CircuitOpen> {
+        breaker.admit()?;
+        Ok(Self {
+            req: Some(req),
+            breaker: Some(breaker.clone()),
+        })
+    }
+    /// Wrap a request no breaker tracks, like stop requests
+    pub fn unguarded(req: Req) -> Self {
+        Self {
+            req: Some(req),
+            breaker: None,
+        }
+    }
+}
+
+impl<Req> Drop for Guarded<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    fn drop(&mut self) {
+        if self.req.is_some()
+            && let Some(breaker) = &self.breaker
+        {
+            breaker.abandon();
+        }
+    }
+}
+
+impl<Req> ControlExecuteMessage for Guarded<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    type Res = Ret<Req>;
+    fn execute(mut self) -> ControlFlow<(), Self::Res> {
+        let Some(req) = self.req.take() else {
+            return ControlFlow::Break(());
+        };
+        let mut unwinding = Unwinding(self.breaker.as_deref());
+        let res = req.execute();
+        unwinding.0 = None;
+        if let Some(breaker) = &self.breaker {
+            match &res {
+                ControlFlow::Continue(res) => breaker.record(res),
+                ControlFlow::Break(()) => breaker.abandon(),
+            }
+        }
+        res
+    }
+    fn priority(&self) -> Priority {
+        self.req.as_ref().map(Req::priority).unwrap_or_default()
+    }
+    fn key(&self) -> Option<u64> {
+        self.req.as_ref().and_then(Req::key)
+    }
+    fn kind(&self) -> Option<u64> {
+        self.req.as_ref().and_then(Req::kind)
+    }
+    fn cost(&self) -> u64 {
+        self.req.as_ref().map_or(1, Req::cost)
+    }
+}
+
+impl<Req, S> StopRunner<Guarded<Req>> for S
+where
+    Req: ControlExecuteMessage,
+    S: StopRunner<Req>,
+{
+    fn get(&self) -> Guarded<Req> {
+        Guarded::unguarded(StopRunner::<Req>::get(self))
+    }
+}
+
+/// One [`CircuitBreaker`] per [`ControlExecuteMessage::key`], made on first use by `make`
+pub struct CircuitBreakers<Res> {
+    make: Box<dyn Fn() -> CircuitBreaker<Res> + Send + Sync>,
+    breakers: Mutex<HashMap<Option<u64>, Arc<CircuitBreaker<Res>>>>,
+}
+
+impl<Res> std::fmt::Debug for CircuitBreakers<Res> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreakers")
+            .field("breakers", &self.breakers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Res> CircuitBreakers<Res> {
+    pub fn new<F>(make: F) -> Self
+    where
+        F: Fn() -> CircuitBreaker<Res> + Send + Sync + 'static,
+    {
+        Self {
+            make: Box::new(make),
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+    /// Breaker of requests with `key`
+    pub fn get(&self, key: Option<u64>) -> Arc<CircuitBreaker<Res>> {
+        self.breakers
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new((self.make)()))
+            .clone()
+    }
+    pub fn state(&self, key: Option<u64>) -> CircuitState {
+        self.get(key).state()
+    }
+    /// Wrap `req` if the breaker of it's key admits it
+    pub fn guard<Req>(&self, req: Req) -> Result<Guarded<Req>, CircuitOpen>
+    where
+        Req: ControlExecuteMessage<Res = Res>,
+    {
+        let breaker = self.get(req.key());
+        Guarded::new(req, &breaker)
+    }
+}
+
+impl<Res> CircuitBreaker<Res> {
+    /// Wrap `req` if this breaker admits it
+    pub fn guard<Req>(self: &Arc<Self>, req: Req) -> Result<Guarded<Req>, CircuitOpen>
+    where
+        Req: ControlExecuteMessage<Res = Res>,
+    {
+        Guarded::new(req, self)
+    }
+}
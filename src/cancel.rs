@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const QUEUED: u8 = 0;
+const RUNNING: u8 = 1;
+const DONE: u8 = 2;
+const DEQUEUED: u8 = 3;
+const SIGNALLED: u8 = 4;
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// Shared between a request and it's [`CancelHandle`], so a running request can notice it
+/// was cancelled and stop early
+#[derive(Debug, Clone)]
+pub struct CancellationToken(Arc<AtomicU8>);
+
+/// What [`CancelHandle::cancel`] did to the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancellation {
+    /// The request was still queued and won't be executed nor answered
+    Dequeued,
+    /// The request is running and it's [`CancellationToken`] was signalled
+    ///
+    /// It may still complete normally, and is answered either way. Only a request checking
+    /// [`CancellationToken::is_cancelled`] stops early, it's response is whatever it returns then
+    Signalled,
+    /// The request already finished, or was already cancelled
+    TooLate,
+}
+
+impl CancellationToken {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(AtomicU8::new(QUEUED)))
+    }
+    /// Token of the request being executed by this thread
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.0.load(Ordering::Acquire), DEQUEUED | SIGNALLED)
+    }
+    /// Mark the request as running, unless it was cancelled while queued
+    pub(crate) fn start(&self) -> bool {
+        self.0
+            .compare_exchange(QUEUED, RUNNING, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+    /// Run `f` as the request owning this token, making it [`CancellationToken::current`]
+    pub(crate) fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        /// Restores the outer token even if `f` panics
+        struct Restore<'a>(&'a CancellationToken, Option<CancellationToken>);
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.replace(self.1.take()));
+                self.0.finish();
+            }
+        }
+        let _restore = Restore(
+            self,
+            CURRENT.with(|current| current.replace(Some(self.clone()))),
+        );
+        f()
+    }
+    pub(crate) fn finish(&self) {
+        // A signalled request stays cancelled
+        let _ = self
+            .0
+            .compare_exchange(RUNNING, DONE, Ordering::AcqRel, Ordering::Acquire);
+    }
+    fn cancel(&self) -> Cancellation {
+        let mut state = self.0.load(Ordering::Acquire);
+        loop {
+            let (next, cancellation) = match state {
+                QUEUED => (DEQUEUED, Cancellation::Dequeued),
+                RUNNING => (SIGNALLED, Cancellation::Signalled),
+                _ => return Cancellation::TooLate,
+            };
+            match self
+                .0
+                .compare_exchange(state, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return cancellation,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+/// Returned when sending a request, lets the caller withdraw it
+#[derive(Debug, Clone)]
+pub struct CancelHandle(CancellationToken);
+
+impl CancelHandle {
+    pub(crate) fn new(token: CancellationToken) -> Self {
+        Self(token)
+    }
+    pub fn cancel(&self) -> Cancellation {
+        self.0.cancel()
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+}
@@ -0,0 +1,397 @@
+//! Graphs of tasks executed on a pool, each one made from the results of the tasks it depends on
+
+use crate::cancel::Cancellation;
+use crate::pool::{Pool, PoolApi, PoolSendError, Seq, Ticket};
+use crate::runner::{ControlExecuteMessage, panic_message};
+use crate::task::{Failure, StopTask, Task};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Tells dags apart, so a [`TaskId`] only works with the dag it came from
+static NEXT_DAG: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a task of a [`Dag`], only the dag that made it takes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskId {
+    dag: u64,
+    index: usize,
+}
+
+impl TaskId {
+    /// Position of the task in the order they were added
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+impl Display for TaskId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// What a [`Dag`] does once a task fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnFailure {
+    /// Skip the tasks depending on the failed one, the others go on
+    #[default]
+    SkipDependents,
+    /// Stop dispatching tasks and cancel the ones already sent
+    FailFast,
+}
+
+/// Why a task of a [`Dag`] has no result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskError<E> {
+    /// The task's request returned an error
+    Failed(E),
+    /// The task's request, or the `make_req` making it, panicked with this message
+    Panicked(String),
+    /// The task's request returned [`ControlFlow::Break`](std::ops::ControlFlow::Break)
+    Stopped,
+    /// The task depends on the failed one, with [`OnFailure::SkipDependents`]
+    Skipped(TaskId),
+    /// The task was cancelled or never sent after another one failed, with
+    /// [`OnFailure::FailFast`]
+    Cancelled,
+}
+
+impl<E: Display> Display for TaskError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Failed(error) => write!(f, "Task failed: {error}"),
+            Self::Panicked(message) => write!(f, "Task panicked: {message}"),
+            Self::Stopped => write!(f, "Task stopped"),
+            Self::Skipped(failed) => write!(f, "Task skipped, task {failed} failed"),
+            Self::Cancelled => write!(f, "Task cancelled"),
+        }
+    }
+}
+
+impl<E> std::error::Error for TaskError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Failed(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Returned instead of running a [`Dag`] whose tasks depend on each other, each task of the
+/// cycle depends on the next one and the last one on the first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError(pub Vec<TaskId>);
+
+impl Display for CycleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tasks depend on each other:")?;
+        for task in &self.0 {
+            write!(f, " {task}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// Request of the dag's pool, no task is passed along
+type Job<Req> = Task<Req, Infallible>;
+
+impl<E> From<Failure<E, Infallible>> for TaskError<E> {
+    fn from(failure: Failure<E, Infallible>) -> Self {
+        match failure {
+            Failure::Failed(error) => Self::Failed(error),
+            Failure::Panicked(message) => Self::Panicked(message),
+            Failure::Stopped => Self::Stopped,
+            Failure::Passed(never) => match never {},
+        }
+    }
+}
+
+/// Makes a task's request from the results of the tasks it depends on
+type MakeReq<Req, T> = Box<dyn FnOnce(&[&T]) -> Req>;
+
+struct Node<Req, T> {
+    parents: Vec<TaskId>,
+    make_req: MakeReq<Req, T>,
+}
+
+/// Tasks executed on a pool once the tasks they depend on succeeded
+///
+/// A task is sent as soon as it's last dependency finishes, with the results of it's
+/// dependencies in the order they were declared
+pub struct Dag<Req, T, E> {
+    id: u64,
+    nodes: Vec<Node<Req, T>>,
+    on_failure: OnFailure,
+    runners: usize,
+    _error: PhantomData<fn() -> E>,
+}
+
+impl<Req, T, E> std::fmt::Debug for Dag<Req, T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dag")
+            .field("tasks", &self.nodes.len())
+            .field("on_failure", &self.on_failure)
+            .field("runners", &self.runners)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Req, T, E> Default for Dag<Req, T, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Req, T, E> Dag<Req, T, E> {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_DAG.fetch_add(1, Ordering::Relaxed),
+            nodes: Vec::new(),
+            on_failure: OnFailure::default(),
+            runners: 1,
+            _error: PhantomData,
+        }
+    }
+    pub fn on_failure(mut self, on_failure: OnFailure) -> Self {
+        self.on_failure = on_failure;
+        self
+    }
+    /// Execute the tasks on a pool of `runners` runners, 1 by default
+    pub fn runners(mut self, runners: usize) -> Self {
+        self.runners = runners.max(1);
+        self
+    }
+    fn task_id(&self, index: usize) -> TaskId {
+        TaskId {
+            dag: self.id,
+            index,
+        }
+    }
+    fn check_owned(&self, task: TaskId) {
+        assert!(task.dag == self.id, "task {task} belongs to another dag");
+    }
+    /// Add a task depending on `parents`, whose request `make_req` makes from their results
+    ///
+    /// # Panics
+    ///
+    /// If one of `parents` was added to another dag
+    pub fn task<F>(&mut self, parents: &[TaskId], make_req: F) -> TaskId
+    where
+        F: FnOnce(&[&T]) -> Req + 'static,
+    {
+        for &parent in parents {
+            self.check_owned(parent);
+        }
+        self.nodes.push(Node {
+            parents: parents.to_vec(),
+            make_req: Box::new(make_req),
+        });
+        self.task_id(self.nodes.len() - 1)
+    }
+    /// Make `task` depend on `parent` too, it's result comes after the ones of the earlier
+    /// dependencies
+    ///
+    /// # Panics
+    ///
+    /// If `task` or `parent` was added to another dag
+    pub fn depend(&mut self, task: TaskId, parent: TaskId) {
+        self.check_owned(task);
+        self.check_owned(parent);
+        self.nodes[task.index].parents.push(parent);
+    }
+
+    /// Tasks each task is a dependency of, and how many dependencies each one has, or the first
+    /// cycle found
+    fn check(&self) -> Result<(Vec<Vec<TaskId>>, Vec<usize>), CycleError> {
+        let mut children = vec![Vec::new(); self.nodes.len()];
+        let waiting: Vec<_> = self.nodes.iter().map(|node| node.parents.len()).collect();
+        for (id, node) in self.nodes.iter().enumerate() {
+            for parent in &node.parents {
+                children[parent.index].push(self.task_id(id));
+            }
+        }
+        // Tasks left once the ones whose dependencies can all finish are removed wait on a cycle
+        let mut left = waiting.clone();
+        let mut ready: Vec<_> = (0..left.len()).filter(|&id| left[id] == 0).collect();
+        while let Some(id) = ready.pop() {
+            for child in &children[id] {
+                left[child.index] -= 1;
+                if left[child.index] == 0 {
+                    ready.push(child.index);
+                }
+            }
+        }
+        let Some(start) = left.iter().position(|&n| n > 0) else {
+            return Ok((children, waiting));
+        };
+        // Every task left has a dependency left, following them comes back to a task seen
+        let mut path = vec![start];
+        loop {
+            let last = *path.last().unwrap();
+            let parent = self.nodes[last]
+                .parents
+                .iter()
+                .find(|parent| left[parent.index] > 0)
+                .unwrap()
+                .index;
+            if let Some(at) = path.iter().position(|&id| id == parent) {
+                let cycle = path[at..].iter().map(|&id| self.task_id(id)).collect();
+                return Err(CycleError(cycle));
+            }
+            path.push(parent);
+        }
+    }
+}
+
+impl<Req, T, E> Dag<Req, T, E>
+where
+    Req: ControlExecuteMessage<Res = Result<T, E>> + 'static,
+    T: std::fmt::Debug + Send + 'static,
+    E: std::fmt::Debug + Send + 'static,
+{
+    /// Execute every task, returning their results once all of them finished
+    ///
+    /// A task whose `make_req` panics fails with [`TaskError::Panicked`] without being sent
+    pub fn run(self) -> Result<DagResults<T, E>, CycleError> {
+        let (children, mut waiting) = self.check()?;
+        let mut nodes: Vec<_> = self.nodes.into_iter().map(Some).collect();
+        let mut results: Vec<Option<Result<T, TaskError<E>>>> =
+            std::iter::repeat_with(|| None).take(nodes.len()).collect();
+        let mut ready: VecDeque<_> = (0..nodes.len()).filter(|&id| waiting[id] == 0).collect();
+        let mut sent: HashMap<Seq, (usize, Ticket)> = HashMap::new();
+        let mut failed = false;
+        let pool: PoolApi<Job<Req>> = Pool::new(self.runners).start();
+        loop {
+            // A task whose request couldn't be made finishes right away
+            let mut unmade = None;
+            while !failed && let Some(id) = ready.pop_front() {
+                let Node { parents, make_req } = nodes[id].take().unwrap();
+                let parents: Vec<_> = parents
+                    .iter()
+                    .map(|parent| match &results[parent.index] {
+                        Some(Ok(res)) => res,
+                        _ => unreachable!("dependencies succeeded"),
+                    })
+                    .collect();
+                let req = match std::panic::catch_unwind(AssertUnwindSafe(|| make_req(&parents))) {
+                    Ok(req) => req,
+                    Err(panic) => {
+                        unmade = Some((id, Err(TaskError::Panicked(panic_message(&*panic)))));
+                        break;
+                    }
+                };
+                let ticket = match pool.send(Task::run(req)) {
+                    Ok(ticket) => ticket,
+                    Err(PoolSendError::Stopped(_)) => unreachable!("the pool is running"),
+                    // The dag's runners are default ones, taking every kind
+                    Err(PoolSendError::Unhandled(_)) => unreachable!("runners handle every kind"),
+                };
+                sent.insert(ticket.seq(), (id, ticket));
+            }
+            let (id, res) = match unmade {
+                Some(unmade) => unmade,
+                None if sent.is_empty() => break,
+                None => {
+                    let (seq, res) = pool.recv_tagged().unwrap();
+                    let Some((id, _)) = sent.remove(&seq) else {
+                        continue;
+                    };
+                    (id, res.map_err(TaskError::from))
+                }
+            };
+            let ok = res.is_ok();
+            results[id] = Some(res);
+            if ok {
+                for child in &children[id] {
+                    waiting[child.index] -= 1;
+                    if waiting[child.index] == 0 && results[child.index].is_none() {
+                        ready.push_back(child.index);
+                    }
+                }
+                continue;
+            }
+            match self.on_failure {
+                OnFailure::SkipDependents => {
+                    // They can't have been sent, they were waiting on this one
+                    let mut skip = children[id].clone();
+                    while let Some(child) = skip.pop() {
+                        if results[child.index].is_none() {
+                            let failed = TaskId {
+                                dag: self.id,
+                                index: id,
+                            };
+                            results[child.index] = Some(Err(TaskError::Skipped(failed)));
+                            skip.extend_from_slice(&children[child.index]);
+                        }
+                    }
+                }
+                OnFailure::FailFast if !failed => {
+                    failed = true;
+                    // Requests removed before they started get no response
+                    sent.retain(|_, (id, ticket)| {
+                        let dequeued = ticket.cancel() == Cancellation::Dequeued;
+                        if dequeued {
+                            results[*id] = Some(Err(TaskError::Cancelled));
+                        }
+                        !dequeued
+                    });
+                }
+                OnFailure::FailFast => {}
+            }
+        }
+        if let Ok(closer) = pool.stop_and_close() {
+            let _ = closer.close_capture(&StopTask);
+        }
+        Ok(DagResults {
+            dag: self.id,
+            results: results
+                .into_iter()
+                .map(|res| res.unwrap_or(Err(TaskError::Cancelled)))
+                .collect(),
+        })
+    }
+}
+
+/// Result of each task of a [`Dag`]
+#[derive(Debug)]
+pub struct DagResults<T, E> {
+    dag: u64,
+    results: Vec<Result<T, TaskError<E>>>,
+}
+
+impl<T, E> DagResults<T, E> {
+    /// # Panics
+    ///
+    /// If `task` was added to another dag
+    pub fn get(&self, task: TaskId) -> &Result<T, TaskError<E>> {
+        assert!(task.dag == self.dag, "task {task} belongs to another dag");
+        &self.results[task.index]
+    }
+    /// Whether every task succeeded
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(Result::is_ok)
+    }
+    /// Tasks that didn't succeed, with why
+    pub fn errors(&self) -> impl Iterator<Item = (TaskId, &TaskError<E>)> {
+        self.results.iter().enumerate().filter_map(|(index, res)| {
+            let task = TaskId {
+                dag: self.dag,
+                index,
+            };
+            res.as_ref().err().map(|e| (task, e))
+        })
+    }
+    /// Results in the order the tasks were added
+    pub fn into_vec(self) -> Vec<Result<T, TaskError<E>>> {
+        self.results
+    }
+}
@@ -0,0 +1,187 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+
+use crate::runner::{ControlExecuteMessage, Ret};
+
+/// Executes a request through the layers after the current one
+pub type Next<'a, Req> = &'a dyn Fn(Req) -> ControlFlow<(), Ret<Req>>;
+
+/// Wraps the execution of every request of a runner, see [`Layers`]
+///
+/// Closures taking the request and [`Next`] are layers too
+pub trait Layer<Req>: Send + Sync
+where
+    Req: ControlExecuteMessage,
+{
+    fn call(&self, req: Req, next: Next<'_, Req>) -> ControlFlow<(), Ret<Req>>;
+}
+
+impl<Req, F> Layer<Req> for F
+where
+    Req: ControlExecuteMessage,
+    F: Fn(Req, Next<'_, Req>) -> ControlFlow<(), Ret<Req>> + Send + Sync,
+{
+    fn call(&self, req: Req, next: Next<'_, Req>) -> ControlFlow<(), Ret<Req>> {
+        self(req, next)
+    }
+}
+
+/// Stack of [`Layer`]s given to a runner, the first one added is the outermost
+///
+/// Runners executing requests in batches don't take layers, they can't wrap a batch
+pub struct Layers<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    layers: Vec<Box<dyn Layer<Req>>>,
+}
+
+impl<Req> Debug for Layers<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Layers")
+            .field("len", &self.layers.len())
+            .finish()
+    }
+}
+
+impl<Req> Default for Layers<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    fn default() -> Self {
+        Self { layers: Vec::new() }
+    }
+}
+
+impl<Req> Layers<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add `layer` inside the layers added before it
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Req> + 'static,
+    {
+        self.layers.push(Box::new(layer));
+        self
+    }
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+    pub(crate) fn execute(&self, req: Req) -> ControlFlow<(), Ret<Req>> {
+        self.execute_from(0, req)
+    }
+    fn execute_from(&self, i: usize, req: Req) -> ControlFlow<(), Ret<Req>> {
+        match self.layers.get(i) {
+            Some(layer) => layer.call(req, &|req| self.execute_from(i + 1, req)),
+            None => req.execute(),
+        }
+    }
+}
+
+/// Reports how long every request took to `report`
+pub struct Timing<F> {
+    report: F,
+}
+
+impl<F> Timing<F>
+where
+    F: Fn(Duration) + Send + Sync,
+{
+    pub fn new(report: F) -> Self {
+        Self { report }
+    }
+}
+
+impl<Req, F> Layer<Req> for Timing<F>
+where
+    Req: ControlExecuteMessage,
+    F: Fn(Duration) + Send + Sync,
+{
+    fn call(&self, req: Req, next: Next<'_, Req>) -> ControlFlow<(), Ret<Req>> {
+        let start = Instant::now();
+        let res = next(req);
+        (self.report)(start.elapsed());
+        res
+    }
+}
+
+/// Turns a panicking request into the response `recover` makes from the panic, instead of
+/// taking the runner down
+pub struct CatchPanic<F> {
+    recover: F,
+}
+
+impl<F> CatchPanic<F> {
+    pub fn new(recover: F) -> Self {
+        Self { recover }
+    }
+}
+
+impl<Req, F> Layer<Req> for CatchPanic<F>
+where
+    Req: ControlExecuteMessage,
+    F: Fn(Box<dyn Any + Send>) -> Ret<Req> + Send + Sync,
+{
+    fn call(&self, req: Req, next: Next<'_, Req>) -> ControlFlow<(), Ret<Req>> {
+        std::panic::catch_unwind(AssertUnwindSafe(|| next(req)))
+            .unwrap_or_else(|panic| ControlFlow::Continue((self.recover)(panic)))
+    }
+}
+
+/// Writes a line to `sink` when a request starts and when it ends, tagged with the thread
+/// executing it
+pub struct Trace<F> {
+    sink: F,
+}
+
+impl<F> Trace<F>
+where
+    F: Fn(std::fmt::Arguments<'_>) + Send + Sync,
+{
+    pub fn new(sink: F) -> Self {
+        Self { sink }
+    }
+}
+
+impl Trace<fn(std::fmt::Arguments<'_>)> {
+    /// Trace to standard error
+    pub fn stderr() -> Self {
+        Self {
+            sink: |line| eprintln!("{line}"),
+        }
+    }
+}
+
+impl<Req, F> Layer<Req> for Trace<F>
+where
+    Req: ControlExecuteMessage + Debug,
+    F: Fn(std::fmt::Arguments<'_>) + Send + Sync,
+{
+    fn call(&self, req: Req, next: Next<'_, Req>) -> ControlFlow<(), Ret<Req>> {
+        let thread = std::thread::current().id();
+        (self.sink)(format_args!("[{thread:?}] start {req:?}"));
+        let start = Instant::now();
+        let res = next(req);
+        let elapsed = start.elapsed();
+        match res {
+            ControlFlow::Continue(_) => {
+                (self.sink)(format_args!("[{thread:?}] done in {elapsed:?}"))
+            }
+            ControlFlow::Break(()) => (self.sink)(format_args!("[{thread:?}] stop in {elapsed:?}")),
+        }
+        res
+    }
+}
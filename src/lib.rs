@@ -3,3 +3,15 @@ pub mod pool;
 pub mod oneshot;
 pub mod aio;
 pub mod runner;
+pub mod priority;
+pub mod cancel;
+pub mod retry;
+pub mod breaker;
+pub mod layer;
+pub mod timeline;
+pub mod pipeline;
+pub mod dag;
+#[cfg(feature = "tower")]
+pub mod service;
+mod task;
+mod trace;
@@ -1,7 +1,12 @@
-use crate::runner::{ControlExecuteMessage, StopRunner};
+use crate::cancel::{CancelHandle, Cancellation};
+use crate::layer::Layers;
+use crate::runner::{Batching, ControlExecuteMessage, DeadlineExceeded, Envelope, StopRunner};
+use crate::timeline::Recorder;
 use std::fmt::Display;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 type Ret<T> = <T as ControlExecuteMessage>::Res;
 
 #[derive(Debug)]
@@ -17,7 +22,7 @@ pub struct OneShot<Req>
 where
     Req: ControlExecuteMessage,
 {
-    req: Req,
+    req: Envelope<Req>,
     chan: oneshot::Sender<Ret<Req>>,
 }
 
@@ -25,11 +30,35 @@ impl<Req> OneShot<Req>
 where
     Req: ControlExecuteMessage,
 {
-    fn unpack(self) -> (Req, oneshot::Sender<Ret<Req>>) {
+    fn unpack(self) -> (Envelope<Req>, oneshot::Sender<Ret<Req>>) {
         (self.req, self.chan)
     }
 }
 
+/// Response of a single request, that may still be cancelled
+#[derive(Debug)]
+pub struct Response<T> {
+    recv: oneshot::Receiver<T>,
+    handle: CancelHandle,
+}
+
+impl<T> Response<T> {
+    /// Wait for the response, fails if the request was cancelled before it started
+    pub fn recv(self) -> Result<T, oneshot::RecvError> {
+        self.recv.recv()
+    }
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, oneshot::RecvTimeoutError> {
+        self.recv.recv_timeout(timeout)
+    }
+    pub fn cancel(&self) -> Cancellation {
+        self.handle.cancel()
+    }
+    #[cfg(feature = "tower")]
+    pub(crate) fn into_receiver(self) -> oneshot::Receiver<T> {
+        self.recv
+    }
+}
+
 pub struct RunnerInternals<Req>
 where
     Req: ControlExecuteMessage,
@@ -37,29 +66,78 @@ where
     reqs: Receiver<OneShot<Req>>,
 }
 
+impl<Req> RunnerInternals<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    /// Take up to `batching.max` requests, waiting up to `batching.wait` after the first one
+    fn take_batch(&self, batching: Batching) -> Vec<OneShot<Req>> {
+        let mut batch = vec![self.reqs.recv().unwrap()];
+        let until = Instant::now() + batching.wait;
+        while batch.len() < batching.max {
+            match self
+                .reqs
+                .recv_timeout(until.saturating_duration_since(Instant::now()))
+            {
+                Ok(msg) => batch.push(msg),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        batch
+    }
+}
+
 pub struct RunnerApi<Req>
 where
     Req: ControlExecuteMessage,
 {
     send_one_shot_req: Sender<OneShot<Req>>,
     thread: JoinHandle<RunnerInternals<Req>>,
+    layers: Option<Arc<Layers<Req>>>,
+    recorder: Option<Recorder>,
+    batched: bool,
 }
 
 impl<Req> RunnerApi<Req>
 where
-    Req: ControlExecuteMessage,
+    Req: ControlExecuteMessage + 'static,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
-    fn _new() -> Self {
+    fn _new(batching: Option<Batching>) -> Self {
         let (send, reqs) = mpsc::channel();
         let internal: RunnerInternals<Req> = RunnerInternals { reqs };
         let thread = std::thread::spawn(move || {
             loop {
+                if let Some(batching) = batching {
+                    let (reqs, chans): (Vec<_>, Vec<_>) = internal
+                        .take_batch(batching)
+                        .into_iter()
+                        .map(OneShot::unpack)
+                        .filter(|(_, chan)| !chan.is_closed())
+                        .unzip();
+                    for (res, chan) in Envelope::execute_batch(reqs).into_iter().zip(chans) {
+                        match res {
+                            std::ops::ControlFlow::Continue(Some(v)) => {
+                                let _ = chan.send(v);
+                            }
+                            std::ops::ControlFlow::Continue(None) => {}
+                            std::ops::ControlFlow::Break(()) => return internal,
+                        }
+                    }
+                    continue;
+                }
                 let (req, chan) = internal.reqs.recv().unwrap().unpack();
+                // Nobody is waiting for the response
+                if chan.is_closed() {
+                    continue;
+                }
                 match req.execute() {
-                    std::ops::ControlFlow::Continue(v) => {
-                        chan.send(v).unwrap();
+                    std::ops::ControlFlow::Continue(Some(v)) => {
+                        // The caller may give up while the request executes
+                        let _ = chan.send(v);
                     }
+                    // Cancelled, dropping `chan` tells the caller
+                    std::ops::ControlFlow::Continue(None) => {}
                     std::ops::ControlFlow::Break(()) => return internal,
                 };
             }
@@ -67,29 +145,74 @@ where
         Self {
             send_one_shot_req: send,
             thread,
+            layers: None,
+            recorder: None,
+            batched: batching.is_some(),
         }
     }
-    fn _send(&self, req: Req) -> Result<oneshot::Receiver<Ret<Req>>, OneShotSendErr<Req>> {
-        let (chan, user_recv) = oneshot::channel();
-        let msg = OneShot { req, chan };
-        self.send_one_shot_req.send(msg).map_err(|e| OneShotSendErr(e.0.req))?;
-        Ok(user_recv)
+    fn _send(&self, req: Envelope<Req>) -> Result<Response<Ret<Req>>, OneShotSendErr<Req>> {
+        let (chan, recv) = oneshot::channel();
+        let handle = req.handle();
+        let msg = OneShot {
+            req: req
+                .layered(self.layers.as_ref())
+                .recorded(self.recorder.as_ref()),
+            chan,
+        };
+        self.send_one_shot_req
+            .send(msg)
+            .map_err(|e| OneShotSendErr(e.0.req.into_inner()))?;
+        Ok(Response { recv, handle })
+    }
+    /// Start a runner that executes requests in batches
+    pub fn with_batching(batching: Batching) -> Self {
+        Self::_new(Some(batching))
+    }
+    /// Execute the requests sent from now on through `layers`
+    ///
+    /// # Panics
+    ///
+    /// If the runner executes requests in batches, layers wrap requests one at a time and
+    /// can't wrap a batch
+    pub fn layers(mut self, layers: Layers<Req>) -> Self {
+        assert!(
+            !self.batched,
+            "batching runners can't execute requests through layers"
+        );
+        self.layers = Some(Arc::new(layers));
+        self
+    }
+    /// Record the timeline of the requests sent from now on with `recorder`
+    pub fn record(mut self, recorder: &Recorder) -> Self {
+        self.recorder = Some(recorder.clone());
+        self
+    }
+    /// Send a request that's skipped and answered with [`DeadlineExceeded`] if it's not executed before `deadline`
+    pub fn send_with_deadline(
+        &self,
+        req: Req,
+        deadline: Instant,
+    ) -> Result<Response<Ret<Req>>, OneShotSendErr<Req>>
+    where
+        Ret<Req>: From<DeadlineExceeded>,
+    {
+        self._send(Envelope::with_deadline(req, deadline))
     }
 }
 
 impl<Req> crate::runner::RunnerApi for RunnerApi<Req>
 where
-    Req: ControlExecuteMessage,
+    Req: ControlExecuteMessage + 'static,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
     type Req = Req;
-    type SendAck = Result<oneshot::Receiver<Ret<Req>>, OneShotSendErr<Req>>;
+    type SendAck = Result<Response<Ret<Req>>, OneShotSendErr<Req>>;
     type CloseResult = Result<RunnerInternals<Req>, OneShotSendErr<Req>>;
     fn send(&self, req: Self::Req) -> Self::SendAck {
-        self._send(req)
+        self._send(Envelope::new(req))
     }
     fn new() -> Self {
-        Self::_new()
+        Self::_new(None)
     }
     // TODO better error
     fn close(self, s: impl StopRunner<Req>) -> Self::CloseResult {
@@ -0,0 +1,337 @@
+//! Chains of runners, each stage executing requests made from the output of the one before
+
+use crate::pool::{Pool, PoolApi};
+use crate::queue::Runner;
+use crate::runner::ControlExecuteMessage;
+use crate::task::{Failure, StopTask, Task};
+use std::fmt::Display;
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, SendError, SyncSender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Items each stage holds when [`Stage::buffer`] isn't set
+const DEFAULT_BUFFER: usize = 16;
+
+/// Why an item didn't make it through a [`Pipeline`], the stages after `stage` skip it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError<E> {
+    /// The stage's request returned an error
+    Failed {
+        stage: usize,
+        error: E,
+    },
+    Panicked {
+        stage: usize,
+        message: String,
+    },
+    /// The stage's request returned [`ControlFlow::Break`](std::ops::ControlFlow::Break), the
+    /// stage goes on with the next item
+    Stopped {
+        stage: usize,
+    },
+}
+
+impl<E: Display> Display for PipelineError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Failed { stage, error } => write!(f, "Stage {stage} failed: {error}"),
+            Self::Panicked { stage, message } => write!(f, "Stage {stage} panicked: {message}"),
+            Self::Stopped { stage } => write!(f, "Stage {stage} stopped"),
+        }
+    }
+}
+
+impl<E> std::error::Error for PipelineError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Failed { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// What comes out of a stage, and out of the pipeline
+pub type Item<T, E> = Result<T, PipelineError<E>>;
+
+/// Request of a stage's runners, items failed in an earlier stage are passed along in their turn
+type StageTask<Req, E> = Task<Req, PipelineError<E>>;
+
+/// Error of an item that failed in `stage` or passed through it
+fn stage_error<E>(stage: usize, failure: Failure<E, PipelineError<E>>) -> PipelineError<E> {
+    match failure {
+        Failure::Failed(error) => PipelineError::Failed { stage, error },
+        Failure::Panicked(message) => PipelineError::Panicked { stage, message },
+        Failure::Stopped => PipelineError::Stopped { stage },
+        Failure::Passed(e) => e,
+    }
+}
+
+/// A step of a [`Pipeline`], making it's requests from the items of the step before
+#[derive(Debug)]
+pub struct Stage<F> {
+    make_req: F,
+    buffer: usize,
+    runners: usize,
+}
+
+impl<F> Stage<F> {
+    pub fn new(make_req: F) -> Self {
+        Self {
+            make_req,
+            buffer: DEFAULT_BUFFER,
+            runners: 1,
+        }
+    }
+    /// Hold at most `buffer` items in the stage and `buffer` more done ones for the next stage,
+    /// 16 by default
+    ///
+    /// The stage before blocks while it's full
+    pub fn buffer(mut self, buffer: usize) -> Self {
+        self.buffer = buffer.max(1);
+        self
+    }
+    /// Execute the stage on a pool of `runners` runners instead of a single queue runner
+    pub fn runners(mut self, runners: usize) -> Self {
+        self.runners = runners.max(1);
+        self
+    }
+}
+
+/// Starts the stages added so far on a receiver of items, returning the receiver of their output
+type Start<In, Out, E> =
+    Box<dyn FnOnce(Receiver<Item<In, E>>, &mut Vec<JoinHandle<()>>) -> Receiver<Item<Out, E>>>;
+
+/// Adds the stages of a [`Pipeline`], see [`Pipeline::builder`]
+pub struct PipelineBuilder<In, Out, E> {
+    stages: usize,
+    start: Start<In, Out, E>,
+}
+
+impl<In, Out, E> std::fmt::Debug for PipelineBuilder<In, Out, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelineBuilder")
+            .field("stages", &self.stages)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<In, Out, E> PipelineBuilder<In, Out, E>
+where
+    In: 'static,
+    Out: Send + 'static,
+    E: std::fmt::Debug + Send + Sync + 'static,
+{
+    /// Add a stage executing the requests `stage` makes from the output of the last one
+    ///
+    /// The requests' errors end up in the pipeline's output as [`PipelineError::Failed`]
+    pub fn stage<Req, T, F>(self, stage: Stage<F>) -> PipelineBuilder<In, T, E>
+    where
+        F: FnMut(Out) -> Req + Send + 'static,
+        Req: ControlExecuteMessage<Res = Result<T, E>> + 'static,
+        T: std::fmt::Debug + Send + 'static,
+    {
+        let index = self.stages;
+        let start = self.start;
+        PipelineBuilder {
+            stages: index + 1,
+            start: Box::new(move |items, threads| {
+                let items = start(items, threads);
+                spawn_stage(index, stage, items, threads)
+            }),
+        }
+    }
+    pub fn start(self) -> Pipeline<In, Out, E> {
+        let (input, items) = mpsc::sync_channel(0);
+        let mut threads = Vec::new();
+        let output = (self.start)(items, &mut threads);
+        Pipeline {
+            input: PipelineSender(input),
+            output,
+            threads,
+        }
+    }
+}
+
+/// Start a stage's runners and the threads feeding them and collecting their responses
+fn spawn_stage<In, Req, T, E, F>(
+    index: usize,
+    stage: Stage<F>,
+    items: Receiver<Item<In, E>>,
+    threads: &mut Vec<JoinHandle<()>>,
+) -> Receiver<Item<T, E>>
+where
+    In: Send + 'static,
+    F: FnMut(In) -> Req + Send + 'static,
+    Req: ControlExecuteMessage<Res = Result<T, E>> + 'static,
+    T: std::fmt::Debug + Send + 'static,
+    E: std::fmt::Debug + Send + Sync + 'static,
+{
+    let Stage {
+        mut make_req,
+        buffer,
+        runners,
+    } = stage;
+    let (send_output, output) = mpsc::sync_channel(buffer);
+    let (send_token, tokens) = mpsc::sync_channel(buffer);
+    // Items keep their order whatever the priority of their requests
+    let task = move |item| match item {
+        Ok(v) => Task::run(make_req(v)).in_order(),
+        Err(e) => Task::pass(e),
+    };
+    let output_of = move |res: Result<T, _>| res.map_err(|e| stage_error(index, e));
+    if runners == 1 {
+        let (send_req, reqs) = mpsc::channel();
+        let (send_res, responses) = mpsc::channel();
+        // Stops once the feeder is gone and it's requests are done
+        threads.push(Runner::bound(reqs, send_res, None).run_thread());
+        threads.push(std::thread::spawn(move || {
+            feed(items, &send_token, task, |task| send_req.send(task).is_ok());
+        }));
+        threads.push(std::thread::spawn(move || {
+            collect(responses.into_iter().map(output_of), tokens, &send_output);
+        }));
+    } else {
+        let mut pool: PoolApi<StageTask<Req, E>> = Pool::new(runners).ordered(buffer).start();
+        let responses = pool.take_responses();
+        threads.push(std::thread::spawn(move || {
+            feed(items, &send_token, task, |task| pool.send(task).is_ok());
+            // The responses of the last requests go to the collector
+            if let Ok(closer) = pool.stop_and_close() {
+                closer.close_tagged(&StopTask);
+            }
+        }));
+        threads.push(std::thread::spawn(move || {
+            collect(
+                responses
+                    .into_iter()
+                    .map(|(_, res)| output_of(res.unwrap())),
+                tokens,
+                &send_output,
+            );
+        }));
+    }
+    output
+}
+
+/// Hand the stage the items as it has room, until they run out or nothing reads it's output
+fn feed<I, T>(
+    items: Receiver<I>,
+    tokens: &SyncSender<()>,
+    mut task: impl FnMut(I) -> T,
+    mut send: impl FnMut(T) -> bool,
+) {
+    for item in items {
+        if tokens.send(()).is_err() || !send(task(item)) {
+            return;
+        }
+    }
+}
+
+/// Pass the stage's output on, making room for another item after each one
+///
+/// Once the output isn't read anymore the stage is drained without passing anything on
+fn collect<T>(
+    responses: impl IntoIterator<Item = T>,
+    tokens: Receiver<()>,
+    output: &SyncSender<T>,
+) {
+    let mut tokens = Some(tokens);
+    for res in responses {
+        let Some(room) = &tokens else {
+            continue;
+        };
+        if output.send(res).is_err() {
+            // Stops the feeder
+            tokens = None;
+            continue;
+        }
+        let _ = room.recv();
+    }
+}
+
+/// Sends items into a [`Pipeline`] from another thread, see [`Pipeline::sender`]
+#[derive(Debug)]
+pub struct PipelineSender<In, E>(SyncSender<Item<In, E>>);
+
+impl<In, E> Clone for PipelineSender<In, E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<In, E> PipelineSender<In, E> {
+    /// Send an item through the pipeline, blocking while the first stage is full
+    ///
+    /// Fails once the pipeline is dropped and the stages stopped
+    pub fn send(&self, item: In) -> Result<(), SendError<In>> {
+        self.0.send(Ok(item)).map_err(|e| match e.0 {
+            Ok(item) => SendError(item),
+            Err(_) => unreachable!("only items are sent"),
+        })
+    }
+}
+
+/// Stages of runners connected by bounded buffers, each executing requests made from the output
+/// of the one before
+///
+/// Items come out in the order they went in. An item failing in a stage skips the later ones
+/// and comes out as a [`PipelineError`]. Shutdown goes through the whole pipeline: once the
+/// input is closed each stage finishes it's items and stops, and once the pipeline is dropped
+/// every stage stops taking items
+#[derive(Debug)]
+pub struct Pipeline<In, Out, E> {
+    input: PipelineSender<In, E>,
+    output: Receiver<Item<Out, E>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<In, E> Pipeline<In, In, E>
+where
+    In: 'static,
+    E: 'static,
+{
+    /// Start a pipeline taking items of type `In` whose stages fail with `E`
+    pub fn builder() -> PipelineBuilder<In, In, E> {
+        PipelineBuilder {
+            stages: 0,
+            start: Box::new(|items, _| items),
+        }
+    }
+}
+
+impl<In, Out, E> Pipeline<In, Out, E> {
+    /// Send an item through the pipeline, blocking while the first stage is full
+    pub fn send(&self, item: In) -> Result<(), SendError<In>> {
+        self.input.send(item)
+    }
+    /// Send items from another thread than the one receiving the output
+    pub fn sender(&self) -> PipelineSender<In, E> {
+        self.input.clone()
+    }
+    /// Receive the next output, fails once the input is closed and every item came out
+    pub fn recv(&self) -> Result<Item<Out, E>, RecvError> {
+        self.output.recv()
+    }
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Item<Out, E>, RecvTimeoutError> {
+        self.output.recv_timeout(timeout)
+    }
+    /// Close the input, wait for the items still in the pipeline and stop every stage
+    ///
+    /// Items sent by the [`PipelineSender`]s still around are waited for too
+    pub fn finish(self) -> Vec<Item<Out, E>> {
+        let Self {
+            input,
+            output,
+            threads,
+        } = self;
+        drop(input);
+        let rest = output.into_iter().collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        rest
+    }
+}
@@ -1,29 +1,100 @@
-use crate::runner::{ControlExecuteMessage, StopRunner};
+use crate::cancel::{CancelHandle, Cancellation};
+use crate::layer::Layers;
+use crate::timeline::Recorder;
+use crate::priority::{Lanes, Priority};
+use crate::runner::{Batching, ControlExecuteMessage, DeadlineExceeded, Envelope, StopRunner};
 use crate::queue::Runner;
+use crate::trace::{Phase, RequestSpan};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::marker::PhantomData;
 use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
 use std::sync::mpsc::{Receiver, RecvError, SendError, Sender, TryRecvError};
 use std::thread::JoinHandle;
+use std::time::Instant;
 
 mod api;
+mod autoscale;
 mod balancer;
 mod close;
+mod fair;
+mod iter;
+mod limit;
+mod manager;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod scope;
+mod spec;
+mod stats;
+mod watchdog;
+#[cfg(feature = "tower")]
+mod service;
 pub use api::*;
+pub use autoscale::*;
 use balancer::*;
 pub use close::*;
+pub use fair::*;
+pub use iter::*;
+pub use limit::*;
+use manager::*;
+#[cfg(feature = "prometheus")]
+pub use prometheus::*;
+use scope::*;
+pub use spec::*;
+pub use stats::*;
+pub use watchdog::*;
+#[cfg(feature = "tower")]
+pub use service::*;
 
 type Ret<T> = <T as ControlExecuteMessage>::Res;
 
+/// Requests each runner may have queued when [`Pool::depth`] isn't set
+const DEFAULT_DEPTH: usize = 2;
+
+/// Sequence number given to every request sent through a [`PoolApi`], in submission order
+pub type Seq = usize;
+
+/// Reply to a request, tagged with it's [`Seq`]
+pub(crate) type Tagged<Req> = (Seq, Reply<Ret<Req>>);
+
+/// What a pool hands back for a request
 #[derive(Debug)]
-pub struct Pooled<T>(usize, T);
+pub(crate) enum Reply<T> {
+    Response(T),
+    /// The request panicked, whoever receives it resumes the panic
+    Panicked(Box<dyn std::any::Any + Send>),
+}
+
+impl<T> Reply<T> {
+    /// The response, resuming the request's panic on this thread if it panicked
+    pub(crate) fn unwrap(self) -> T {
+        match self {
+            Reply::Response(response) => response,
+            Reply::Panicked(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+    /// The response, or the request's panic
+    pub(crate) fn into_result(self) -> std::thread::Result<T> {
+        match self {
+            Reply::Response(response) => Ok(response),
+            Reply::Panicked(panic) => Err(panic),
+        }
+    }
+}
+
+/// Request or response of a pool's runner, carrying the span of the request along
+#[derive(Debug)]
+pub struct Pooled<T>(usize, Seq, T, RequestSpan);
 
 impl<T> Pooled<T> {
-    fn pack(id: usize, v: T) -> Self {
-        Self(id, v)
+    fn pack(id: usize, seq: Seq, v: T, span: RequestSpan) -> Self {
+        Self(id, seq, v, span)
     }
-    fn unpack(self) -> (usize, T) {
-        (self.0, self.1)
+    fn unpack(self) -> (usize, Seq, T, RequestSpan) {
+        (self.0, self.1, self.2, self.3)
     }
 }
 
@@ -31,12 +102,90 @@ impl<T> ControlExecuteMessage for Pooled<T>
 where
     T: ControlExecuteMessage,
 {
-    type Res = Pooled<Ret<T>>;
+    /// Panics are caught, so the runner survives them
+    type Res = Pooled<Executed<Ret<T>>>;
     fn execute(self) -> std::ops::ControlFlow<(), Self::Res> {
-        match self.1.execute() {
-            std::ops::ControlFlow::Break(()) => std::ops::ControlFlow::Break(()),
-            std::ops::ControlFlow::Continue(c) => {
-                std::ops::ControlFlow::Continue(Pooled::pack(self.0, c))
+        let Pooled(id, seq, req, span) = self;
+        span.started(id);
+        let busy = Busy::start();
+        let start = Instant::now();
+        let executed = span.in_scope(|| std::panic::catch_unwind(AssertUnwindSafe(|| req.execute())));
+        let outcome = match executed {
+            Ok(ControlFlow::Break(())) => return ControlFlow::Break(()),
+            Ok(ControlFlow::Continue(c)) => Ok(c),
+            Err(panic) => Err(panic),
+        };
+        let took = start.elapsed();
+        drop(busy);
+        span.finished(id, took, outcome.is_err());
+        ControlFlow::Continue(Pooled::pack(id, seq, Executed { took, outcome }, span))
+    }
+    fn priority(&self) -> Priority {
+        self.2.priority()
+    }
+    fn key(&self) -> Option<u64> {
+        self.2.key()
+    }
+    fn kind(&self) -> Option<u64> {
+        self.2.kind()
+    }
+    fn cost(&self) -> u64 {
+        self.2.cost()
+    }
+    fn execute_batch(batch: Vec<Self>) -> Vec<ControlFlow<(), Self::Res>> {
+        let (tags, reqs): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|Pooled(id, seq, req, span)| ((id, seq, span), req))
+            .unzip();
+        for (id, _, span) in &tags {
+            span.started(*id);
+        }
+        let busy = Busy::start();
+        let start = Instant::now();
+        let executed = std::panic::catch_unwind(AssertUnwindSafe(|| T::execute_batch(reqs)));
+        drop(busy);
+        // Requests of a batch share it's time
+        let took = start.elapsed() / u32::try_from(tags.len()).unwrap_or(u32::MAX).max(1);
+        match executed {
+            Ok(executed) => tags
+                .into_iter()
+                .zip(executed)
+                .map(|((id, seq, span), res)| match res {
+                    ControlFlow::Continue(c) => {
+                        span.finished(id, took, false);
+                        ControlFlow::Continue(Pooled::pack(
+                            id,
+                            seq,
+                            Executed {
+                                took,
+                                outcome: Ok(c),
+                            },
+                            span,
+                        ))
+                    }
+                    ControlFlow::Break(()) => ControlFlow::Break(()),
+                })
+                .collect(),
+            // Every request of the batch failed, only the first one gets the panic
+            Err(panic) => {
+                let mut panic = Some(panic);
+                tags.into_iter()
+                    .map(|(id, seq, span)| {
+                        let panic = panic
+                            .take()
+                            .unwrap_or_else(|| Box::new("another request of the batch panicked"));
+                        span.finished(id, took, true);
+                        ControlFlow::Continue(Pooled::pack(
+                            id,
+                            seq,
+                            Executed {
+                                took,
+                                outcome: Err(panic),
+                            },
+                            span,
+                        ))
+                    })
+                    .collect()
             }
         }
     }
@@ -60,14 +209,20 @@ impl<T> Default for Chan<T> {
     }
 }
 
-pub struct PoolConDef<Req> {
-    pooled_chan: Chan<Pooled<Req>>,
+pub struct PoolConDef<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    pooled_chan: Chan<Pooled<Envelope<Req>>>,
 }
 
 #[derive(Debug)]
-pub struct PoolCon<Req> {
+pub struct PoolCon<Req>
+where
+    Req: ControlExecuteMessage,
+{
     _thread: std::thread::JoinHandle<()>,
-    send_pooled_req: Sender<Pooled<Req>>,
+    send_pooled_req: Sender<Pooled<Envelope<Req>>>,
 }
 
 impl<Req> PoolConDef<Req>
@@ -80,8 +235,27 @@ where
             pooled_chan: Chan::new(),
         }
     }
-    fn run(self, send_pooled_res: Sender<Pooled<Ret<Req>>>) -> PoolCon<Req> {
-        let thread = Runner::make_bound(self.pooled_chan.recv, send_pooled_res);
+    /// Start the runner reporting to `beat` as it's runner of `generation`
+    ///
+    /// # Safety
+    ///
+    /// See [`Spawner::spawn`]
+    unsafe fn run(
+        self,
+        send_pooled_res: Sender<Pooled<Executed<Option<Ret<Req>>>>>,
+        batching: Option<Batching>,
+        spawner: &Spawner,
+        beat: Arc<Beat>,
+        generation: usize,
+    ) -> PoolCon<Req> {
+        let runner = Runner::bound(self.pooled_chan.recv, send_pooled_res, batching);
+        // SAFETY: upheld by the caller
+        let thread = unsafe {
+            spawner.spawn(move || {
+                Beat::install(beat, generation);
+                runner.run()
+            })
+        };
         PoolCon {
             _thread: thread,
             send_pooled_req: self.pooled_chan.send,
@@ -89,47 +263,197 @@ where
     }
 }
 
-impl<Req> PoolCon<Req> {
-    fn send(&self, req: Pooled<Req>) -> Result<(), SendError<Pooled<Req>>> {
+impl<Req> PoolCon<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    fn send(
+        &self,
+        req: Pooled<Envelope<Req>>,
+    ) -> Result<(), SendError<Pooled<Envelope<Req>>>> {
         self.send_pooled_req.send(req)
     }
 }
 
-pub struct Pool<Req, const CCOUNT: usize>
+pub struct Pool<Req>
 where
     Req: ControlExecuteMessage,
 {
-    user_request_channel: Chan<ControlFlow<(), Req>>,
-    user_response_channel: Chan<Ret<Req>>,
-    pooled_request_channel: [PoolConDef<Req>; CCOUNT],
-    pooled_response_channel: Chan<Pooled<Ret<Req>>>,
+    user_request_channel: Chan<ControlFlow<(), Command<Req>>>,
+    user_response_channel: Chan<Tagged<Req>>,
+    pooled_request_channel: Vec<PoolConDef<Req>>,
+    pooled_response_channel: Chan<Pooled<Executed<Option<Ret<Req>>>>>,
+    ordered: Option<usize>,
+    depth: usize,
+    rate: Option<Rate>,
+    rate_per_key: Option<Rate>,
+    batching: Option<Batching>,
+    layers: Option<Layers<Req>>,
+    hooks: Option<Box<dyn PoolHooks>>,
+    recorder: Option<Recorder>,
+    watchdog: Option<Watchdog>,
+    autoscale: Option<Autoscale>,
+    specs: Vec<RunnerSpec>,
+    strategy: Strategy,
 }
 
-impl<Req, const CCOUNT: usize> Default for Pool<Req, CCOUNT>
+impl<Req> Pool<Req>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
-    fn default() -> Self {
+    /// Pool of `runners` runners, at least one
+    pub fn new(runners: usize) -> Self {
+        let runners = runners.max(1);
         Self {
             user_request_channel: Chan::new(),
             user_response_channel: Chan::new(),
-            pooled_request_channel: [(); CCOUNT].map(|_| PoolConDef::new()),
+            pooled_request_channel: (0..runners).map(|_| PoolConDef::new()).collect(),
             pooled_response_channel: Chan::new(),
+            ordered: None,
+            depth: DEFAULT_DEPTH,
+            rate: None,
+            rate_per_key: None,
+            batching: None,
+            layers: None,
+            hooks: None,
+            recorder: None,
+            watchdog: None,
+            autoscale: None,
+            specs: vec![RunnerSpec::default(); runners],
+            strategy: Strategy::default(),
         }
     }
-}
 
-impl<Req, const CCOUNT: usize> Pool<Req, CCOUNT>
-where
-    Req: ControlExecuteMessage,
-    Ret<Req>: std::fmt::Debug + Send + 'static,
-{
-    pub fn new() -> Self {
-        Self::default()
+    /// Release responses in submission order, holding at most `bound` of them back
+    ///
+    /// The pool stops dispatching new requests while `bound` responses are held back. Requests
+    /// left behind by a replaced runner are skipped, see [`Watchdog::replace`]
+    pub fn ordered(mut self, bound: usize) -> Self {
+        self.ordered = Some(bound);
+        self
+    }
+
+    /// Limit how many requests each runner may have queued, the rest wait in the dispatcher
+    /// where higher priority requests can overtake them
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    /// Limit how fast the pool dispatches requests, requests over the limit wait in the dispatcher
+    pub fn rate_limit(mut self, rate: Rate) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Make the pool's runners execute requests in batches
+    ///
+    /// Each runner may have at least `batching.max` requests queued, regardless of [`Pool::depth`]
+    ///
+    /// # Panics
+    ///
+    /// If [`Pool::layers`] are set, layers wrap requests one at a time and can't wrap a batch
+    pub fn batching(mut self, batching: Batching) -> Self {
+        assert!(
+            self.layers.is_none(),
+            "batching runners can't execute requests through layers"
+        );
+        self.batching = Some(batching);
+        self
+    }
+
+    /// Execute every request through `layers`
+    ///
+    /// # Panics
+    ///
+    /// If [`Pool::batching`] is set, layers wrap requests one at a time and can't wrap a batch
+    pub fn layers(mut self, layers: Layers<Req>) -> Self {
+        assert!(
+            self.batching.is_none(),
+            "batching runners can't execute requests through layers"
+        );
+        self.layers = Some(layers);
+        self
+    }
+
+    /// Call `hooks` as requests go through the pool
+    pub fn hooks<H>(mut self, hooks: H) -> Self
+    where
+        H: PoolHooks + 'static,
+    {
+        self.hooks = Some(Box::new(hooks));
+        self
+    }
+
+    /// Record the timeline of every request with `recorder`
+    pub fn record(mut self, recorder: &Recorder) -> Self {
+        self.recorder = Some(recorder.clone());
+        self
+    }
+
+    /// Watch the runners for requests executing too long
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Make runner `id` take what `spec` allows, runners added later by
+    /// [`PoolApi::resize`] take any request
+    ///
+    /// # Panics
+    ///
+    /// If `id` isn't less than the number of runners the pool starts with
+    pub fn runner(mut self, id: usize, spec: RunnerSpec) -> Self {
+        self.specs[id] = spec;
+        self
     }
 
-    pub fn start(self) -> PoolApi<Req, CCOUNT> {
+    /// Pick runners for requests with `strategy`, [`Strategy::LeastLoaded`] by default
+    ///
+    /// # Panics
+    ///
+    /// If the `alpha` of [`Strategy::Latency`] isn't in `(0, 1]`
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        if let Strategy::Latency { alpha } = strategy {
+            assert!(
+                alpha > 0.0 && alpha <= 1.0,
+                "latency alpha must be in (0, 1], got {alpha}"
+            );
+        }
+        self.strategy = strategy;
+        self
+    }
+
+    /// Grow and shrink the pool with it's load, starting from it's initial runners
+    ///
+    /// # Panics
+    ///
+    /// If `autoscale` doesn't scale down below the utilisation it scales up at
+    pub fn autoscale(mut self, autoscale: Autoscale) -> Self {
+        autoscale.check();
+        self.autoscale = Some(autoscale);
+        self
+    }
+
+    /// Limit how fast the pool dispatches requests of each [`ControlExecuteMessage::key`]
+    pub fn rate_limit_per_key(mut self, rate: Rate) -> Self {
+        self.rate_per_key = Some(rate);
+        self
+    }
+
+    pub fn start(self) -> PoolApi<Req>
+    where
+        Req: 'static,
+    {
+        // SAFETY: `Req` and it's response are `'static`
+        unsafe { self.launch(Spawner::Detached) }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Spawner::spawn`]
+    unsafe fn launch(self, spawner: Spawner) -> PoolApi<Req> {
         let Chan {
             send: send_pooled_response,
             recv: recv_pooled_response,
@@ -143,51 +467,76 @@ where
             recv: user_recv_response,
         } = self.user_response_channel;
 
+        let balancer = Arc::new(PoolBalancer::new(self.specs, self.strategy));
+        let stats = Arc::new(Stats::default());
+        let mut beats = balancer.beats().into_iter();
+        // SAFETY: upheld by the caller
         let runners = self
             .pooled_request_channel
-            .map(|con_def| con_def.run(send_pooled_response.clone()));
-
-        let manager_thread = std::thread::spawn(move || {
-            let pb = PoolBalancer::<CCOUNT>::new();
-            loop {
-                match recv_user_req.try_recv() {
-                    Err(TryRecvError::Empty) => {}
-                    Err(TryRecvError::Disconnected) => {
-                        panic!("Channel closed")
-                    }
-                    Ok(ControlFlow::Continue(req)) => {
-                        let runner_ref = pb.send();
-                        let pooled_req = Pooled::pack(runner_ref.id, req);
-                        runners[runner_ref.id].send(pooled_req).unwrap();
-                        continue;
-                    }
-                    Ok(ControlFlow::Break(())) => {
-                        return close::PoolCloserDef {
-                            balancer: pb,
-                            recv_pooled_response,
-                            runners,
-                            user_send_response,
-                        };
-                    }
-                };
-                match recv_pooled_response.try_recv() {
-                    Err(TryRecvError::Empty) => {}
-                    Err(TryRecvError::Disconnected) => {
-                        panic!("Channel closed")
-                    }
-                    Ok(pooled_response) => {
-                        let (runner_id, response) = pooled_response.unpack();
-                        pb.done(runner_id);
-                        user_send_response.send(response).unwrap();
-                    }
-                }
-                std::thread::yield_now();
-            }
+            .into_iter()
+            .map(|con_def| unsafe {
+                con_def.run(
+                    send_pooled_response.clone(),
+                    self.batching,
+                    &spawner,
+                    beats.next().unwrap(),
+                    0,
+                )
+            })
+            .collect();
+        let watchdog = self.watchdog.map(|watchdog| {
+            let (stop, stopped) = std::sync::mpsc::channel();
+            let balancer = balancer.clone();
+            let stats = stats.clone();
+            // SAFETY: upheld by the caller
+            unsafe { spawner.spawn(move || watchdog.watch(&balancer, &stats, &stopped)) };
+            stop
         });
 
+        let next_seq = Arc::new(AtomicUsize::new(0));
+        let layers = self.layers.map(Arc::new);
+        let manager = Manager {
+            runners,
+            balancer: balancer.clone(),
+            recv_pooled_response,
+            user_send_response,
+            reorder: self.ordered.map(Reorder::new),
+            backlog: Fair::new(),
+            parked: VecDeque::new(),
+            owners: HashMap::new(),
+            clients: HashMap::new(),
+            depth: self.depth.max(self.batching.map_or(0, |b| b.max)),
+            limiter: (self.rate.is_some() || self.rate_per_key.is_some())
+                .then(|| Limiter::new(self.rate, self.rate_per_key)),
+            stats: stats.clone(),
+            hooks: self.hooks,
+            send_pooled_response,
+            batching: self.batching,
+            spawner: spawner.clone(),
+            replaced: Vec::new(),
+            orphaned: HashSet::new(),
+            autoscaler: self.autoscale.map(Autoscaler::new),
+            next_seq: next_seq.clone(),
+            layers: layers.clone(),
+            recorder: self.recorder.clone(),
+            broadcast_of: HashMap::new(),
+            gathers: HashMap::new(),
+            broadcasts: VecDeque::new(),
+            _watchdog: watchdog,
+        };
+        // SAFETY: upheld by the caller
+        let manager_thread = unsafe { spawner.spawn(move || manager.run(recv_user_req)) };
+
         PoolApi {
             send_req: user_send_req,
             recv_res: user_recv_response,
+            next_seq,
+            next_client: AtomicUsize::new(DEFAULT_CLIENT + 1),
+            layers,
+            recorder: self.recorder,
+            balancer,
+            stats,
+            stash: RefCell::new(VecDeque::new()),
             manager_thread,
         }
     }
@@ -1,41 +1,440 @@
 use super::*;
 
-pub struct PoolApi<Req, const N: usize>
+/// Message sent to the pool's manager by it's clients
+pub(crate) enum Command<Req>
 where
     Req: ControlExecuteMessage,
 {
-    pub(crate) send_req: Sender<ControlFlow<(), Req>>,
-    pub(crate) recv_res: Receiver<Ret<Req>>,
-    pub(crate) manager_thread: JoinHandle<PoolCloserDef<Req, N>>,
+    Submit(ClientId, Seq, Envelope<Req>),
+    Join(ClientId, Share, Sender<Tagged<Req>>),
+    Leave(ClientId),
+    Resize(usize),
+    Broadcast(MakeReq<Req>, Sender<Gathered<Ret<Req>>>),
 }
 
-impl<Req, const N: usize> PoolApi<Req, N>
+/// Makes the request of each runner a broadcast goes to
+pub(crate) type MakeReq<Req> = Box<dyn FnMut(usize) -> Req + Send>;
+
+/// Responses of a broadcast, or the panic of it's `make_req`
+pub(crate) type Gathered<T> = std::thread::Result<Vec<Option<T>>>;
+
+pub struct PoolApi<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    pub(crate) send_req: Sender<ControlFlow<(), Command<Req>>>,
+    pub(crate) recv_res: Receiver<Tagged<Req>>,
+    /// Shared with the pool's clients, so sequence numbers stay unique
+    pub(crate) next_seq: Arc<AtomicUsize>,
+    pub(crate) next_client: AtomicUsize,
+    pub(crate) layers: Option<Arc<Layers<Req>>>,
+    pub(crate) recorder: Option<Recorder>,
+    pub(crate) balancer: Arc<PoolBalancer>,
+    pub(crate) stats: Arc<Stats>,
+    /// Responses received by an adapter that belong to someone else
+    pub(crate) stash: RefCell<VecDeque<Tagged<Req>>>,
+    pub(crate) manager_thread: JoinHandle<PoolCloserDef<Req>>,
+}
+
+fn untag<Req>(e: SendError<ControlFlow<(), Command<Req>>>) -> SendError<ControlFlow<(), Req>>
+where
+    Req: ControlExecuteMessage,
+{
+    SendError(match e.0 {
+        ControlFlow::Continue(Command::Submit(_, _, env)) => ControlFlow::Continue(env.into_inner()),
+        // Commands without a request of their own fail like stops
+        ControlFlow::Continue(
+            Command::Join(..) | Command::Leave(_) | Command::Resize(_) | Command::Broadcast(..),
+        )
+        | ControlFlow::Break(()) => ControlFlow::Break(()),
+    })
+}
+
+/// Why a pool didn't take a request, with the request
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PoolSendError<Req> {
+    /// The pool is stopped
+    Stopped(Req),
+    /// No runner taking new requests handles the request's [`kind`](ControlExecuteMessage::kind)
+    Unhandled(Req),
+}
+
+impl<Req> PoolSendError<Req> {
+    /// The request the pool didn't take
+    pub fn into_inner(self) -> Req {
+        match self {
+            PoolSendError::Stopped(req) | PoolSendError::Unhandled(req) => req,
+        }
+    }
+}
+impl<Req> std::fmt::Debug for PoolSendError<Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolSendError::Stopped(_) => write!(f, "Stopped(..)"),
+            PoolSendError::Unhandled(_) => write!(f, "Unhandled(..)"),
+        }
+    }
+}
+impl<Req> std::fmt::Display for PoolSendError<Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolSendError::Stopped(_) => write!(f, "Pool stopped"),
+            PoolSendError::Unhandled(_) => write!(f, "No runner handles the request's kind"),
+        }
+    }
+}
+impl<Req> std::error::Error for PoolSendError<Req> {}
+
+pub(crate) fn submit<Req>(
+    send_req: &Sender<ControlFlow<(), Command<Req>>>,
+    next_seq: &AtomicUsize,
+    balancer: &PoolBalancer,
+    client: ClientId,
+    env: Envelope<Req>,
+) -> Result<Ticket, PoolSendError<Req>>
+where
+    Req: ControlExecuteMessage,
+{
+    // Resizing keeps the runners of every kind handled now, so it's dispatched eventually
+    if !balancer.handles(env.kind()) {
+        return Err(PoolSendError::Unhandled(env.into_inner()));
+    }
+    let seq = next_seq.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let env = env.traced(RequestSpan::new(seq, client));
+    let handle = env.handle();
+    send_req
+        .send(ControlFlow::Continue(Command::Submit(client, seq, env)))
+        .map_err(|e| match untag(e).0 {
+            ControlFlow::Continue(req) => PoolSendError::Stopped(req),
+            ControlFlow::Break(()) => unreachable!("a failed submit returns it's request"),
+        })?;
+    Ok(Ticket { seq, handle })
+}
+
+/// Responses of a request broadcast to every runner of a pool, see [`PoolApi::broadcast`]
+#[derive(Debug)]
+pub struct Broadcast<T> {
+    recv: Receiver<Gathered<T>>,
+}
+
+impl<T> Broadcast<T> {
+    /// Wait for every runner's response, in runner order, `None` for the ones that panicked
+    ///
+    /// Fails if the pool stopped before every runner answered, which only happens when it's
+    /// [`Watchdog`] replaced a runner that never returned
+    ///
+    /// # Panics
+    ///
+    /// With the panic of the broadcast's `make_req`, if it panicked
+    pub fn recv(self) -> Result<Vec<Option<T>>, RecvError> {
+        self.recv
+            .recv()
+            .map(|gathered| gathered.unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+    }
+    /// Like [`Broadcast::recv`], failing after `timeout`
+    ///
+    /// # Panics
+    ///
+    /// With the panic of the broadcast's `make_req`, if it panicked
+    pub fn recv_timeout(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<Vec<Option<T>>, std::sync::mpsc::RecvTimeoutError> {
+        self.recv
+            .recv_timeout(timeout)
+            .map(|gathered| gathered.unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+    }
+}
+
+/// Returned when sending a request to a pool, identifies and lets the caller cancel it
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    seq: Seq,
+    handle: CancelHandle,
+}
+
+impl Ticket {
+    /// Sequence number the request's response is tagged with
+    pub fn seq(&self) -> Seq {
+        self.seq
+    }
+    /// Cancel the request, a request removed before it started gets no response
+    ///
+    /// A running request is only signalled, it still gets a response, see
+    /// [`Cancellation::Signalled`]
+    pub fn cancel(&self) -> Cancellation {
+        self.handle.cancel()
+    }
+}
+
+impl<Req> PoolApi<Req>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
-    pub fn send(&self, req: Req) -> Result<(), SendError<ControlFlow<(), Req>>> {
-        self.send_req.send(ControlFlow::Continue(req))
+    fn submit(&self, env: Envelope<Req>) -> Result<Ticket, PoolSendError<Req>> {
+        let env = env
+            .layered(self.layers.as_ref())
+            .recorded(self.recorder.as_ref());
+        submit(
+            &self.send_req,
+            &self.next_seq,
+            &self.balancer,
+            DEFAULT_CLIENT,
+            env,
+        )
+    }
+    /// Send a request to the pool
+    ///
+    /// Fails with [`PoolSendError::Stopped`] once the pool is stopped, or
+    /// [`PoolSendError::Unhandled`] when no runner taking new requests handles it's
+    /// [`kind`](ControlExecuteMessage::kind)
+    pub fn send(&self, req: Req) -> Result<Ticket, PoolSendError<Req>> {
+        self.submit(Envelope::new(req))
+    }
+    /// Send a request that's dispatched before the ones with a lower priority
+    pub fn send_with_priority(
+        &self,
+        req: Req,
+        priority: Priority,
+    ) -> Result<Ticket, PoolSendError<Req>> {
+        self.submit(Envelope::with_priority(req, priority))
+    }
+    /// Send a request that's skipped and answered with [`DeadlineExceeded`] if it's not executed before `deadline`
+    pub fn send_with_deadline(
+        &self,
+        req: Req,
+        deadline: Instant,
+    ) -> Result<Ticket, PoolSendError<Req>>
+    where
+        Ret<Req>: From<DeadlineExceeded>,
+    {
+        self.submit(Envelope::with_deadline(req, deadline))
     }
+    /// Receive a response
+    ///
+    /// # Panics
+    ///
+    /// With the panic of the request, if it panicked
     pub fn recv(&self) -> Result<Ret<Req>, RecvError> {
-        self.recv_res.recv()
+        self.recv_tagged().map(|(_, res)| res)
+    }
+    /// Receive a response together with the sequence number of it's request's [`Ticket`]
+    ///
+    /// # Panics
+    ///
+    /// With the panic of the request, if it panicked
+    pub fn recv_tagged(&self) -> Result<(Seq, Ret<Req>), RecvError> {
+        let stashed = self.stash.borrow_mut().pop_front();
+        let (seq, reply) = match stashed {
+            Some(tagged) => tagged,
+            None => self.recv_res.recv()?,
+        };
+        Ok((seq, reply.unwrap()))
+    }
+
+    /// Make a handle submitting requests through it's own queue, served fairly against the
+    /// pool's other clients, and receiving only the responses to it's own requests
+    pub fn client(&self, share: Share) -> Client<Req> {
+        let (id, recv_res) = self.join(share);
+        Client {
+            id,
+            send_req: self.send_req.clone(),
+            recv_res,
+            next_seq: self.next_seq.clone(),
+            layers: self.layers.clone(),
+            recorder: self.recorder.clone(),
+            balancer: self.balancer.clone(),
+        }
+    }
+
+    /// Snapshot of the pool's queues, runners and finished requests
+    pub fn stats(&self) -> PoolStats {
+        self.stats_handle().stats()
+    }
+    /// Handle reading the pool's stats from other threads, even after the pool stopped
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle::new(&self.stats, &self.balancer)
+    }
+
+    /// Send the request `make_req` makes for each runner taking requests to that runner
+    ///
+    /// `make_req` is called on the pool's dispatcher with the index of each runner, a panic
+    /// there sends nothing and is resumed by [`Broadcast::recv`]. The requests skip the queue
+    /// and the runners' limits, each runner executes it after the requests it already has. A
+    /// paused pool holds the broadcast back until it's resumed, a pool stopped afterwards still
+    /// executes them before it closes
+    pub fn broadcast<F>(
+        &self,
+        make_req: F,
+    ) -> Result<Broadcast<Ret<Req>>, SendError<ControlFlow<(), Req>>>
+    where
+        F: FnMut(usize) -> Req + Send + 'static,
+    {
+        let (send, recv) = std::sync::mpsc::channel();
+        self.send_req
+            .send(ControlFlow::Continue(Command::Broadcast(
+                Box::new(make_req),
+                send,
+            )))
+            .map_err(untag)?;
+        Ok(Broadcast { recv })
+    }
+
+    /// Stop dispatching requests until [`PoolApi::resume`], requests sent meanwhile are queued
+    ///
+    /// Requests already handed to runners finish normally, broadcasts sent meanwhile wait too.
+    /// Closing a paused pool executes the queued requests and broadcasts
+    pub fn pause(&self) {
+        self.stats.set_paused(true);
+    }
+    pub fn resume(&self) {
+        self.stats.set_paused(false);
+    }
+
+    /// Run the pool on `runners` runners from now on, at least one
+    ///
+    /// New runners start right away. Retiring runners take no new requests and exit once the
+    /// ones they were given are done. A runner that's the only one handling a
+    /// [`kind`](ControlExecuteMessage::kind) doesn't retire, nor do the ones before it. With [`Pool::autoscale`] the pool is then kept within it's
+    /// bounds, and the cooldowns count from this resize
+    pub fn resize(&self, runners: usize) {
+        // Sends only fail once the manager is gone, the pool is stopped then
+        let _ = self
+            .send_req
+            .send(ControlFlow::Continue(Command::Resize(runners)));
+    }
+
+    /// Take the pool's responses, to receive them on another thread than the one sending
+    ///
+    /// The responses of [`PoolCloser::close_tagged`] go there too
+    pub(crate) fn take_responses(&mut self) -> Receiver<Tagged<Req>> {
+        std::mem::replace(&mut self.recv_res, std::sync::mpsc::channel().1)
+    }
+
+    /// Register a new client, returning it's id and responses
+    pub(crate) fn join(&self, share: Share) -> (ClientId, Receiver<Tagged<Req>>) {
+        let id = self
+            .next_client
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let (send_res, recv_res) = std::sync::mpsc::channel();
+        // Sends only fail once the manager is gone, the client's sends report it then
+        let _ = self
+            .send_req
+            .send(ControlFlow::Continue(Command::Join(id, share, send_res)));
+        (id, recv_res)
     }
 
     /// Stop execution of pool and take it's reciever for the remaining tasks
-    pub fn stop(self) -> Result<PoolCloseRecvPair<Req, N>, SendError<ControlFlow<(), Req>>> {
-        self.send_req.send(ControlFlow::Break(()))?;
+    ///
+    /// Responses not yet received come first, including the ones held back by an adapter. The
+    /// panic of a request among them is resumed by [`PoolCloser::close_await`], or captured by
+    /// [`PoolCloser::close_capture`]
+    pub fn stop(self) -> Result<PoolCloseRecvPair<Req>, SendError<ControlFlow<(), Req>>> {
+        self.send_req.send(ControlFlow::Break(())).map_err(untag)?;
         let closer_def = self.manager_thread.join().unwrap();
-        let closer = PoolCloser::<Req, N, ReceiverReturned>::from(closer_def);
-        Ok((closer, self.recv_res))
+        let (returned, recv) = std::sync::mpsc::channel();
+        let mut closer = PoolCloser::<Req, ReceiverReturned>::returning(closer_def, returned);
+        // The manager sent everything it had before it stopped
+        for (_, reply) in self.stash.into_inner().into_iter().chain(self.recv_res.try_iter()) {
+            closer.hand_back(reply);
+        }
+        Ok((closer, recv))
     }
 
     /// Stop execution of pool and drop the pool's response reciever
     pub fn stop_and_close(
         self,
-    ) -> Result<PoolCloser<Req, N, ReceiverDropped>, SendError<ControlFlow<(), Req>>> {
-        self.send_req.send(ControlFlow::Break(()))?;
+    ) -> Result<PoolCloser<Req, ReceiverDropped>, SendError<ControlFlow<(), Req>>> {
+        self.send_req.send(ControlFlow::Break(())).map_err(untag)?;
         let closer_def = self.manager_thread.join().unwrap();
-        Ok(PoolCloser::<Req, N, ReceiverDropped>::from(closer_def))
+        Ok(PoolCloser::<Req, ReceiverDropped>::from(closer_def))
     }
 }
 
+/// Submits requests to a pool with it's own queue and responses, see [`PoolApi::client`]
+///
+/// Requests still queued when the client is dropped are executed, their responses dropped
+pub struct Client<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    id: ClientId,
+    send_req: Sender<ControlFlow<(), Command<Req>>>,
+    recv_res: Receiver<Tagged<Req>>,
+    next_seq: Arc<AtomicUsize>,
+    layers: Option<Arc<Layers<Req>>>,
+    recorder: Option<Recorder>,
+    balancer: Arc<PoolBalancer>,
+}
+
+impl<Req> Client<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+    fn submit(&self, env: Envelope<Req>) -> Result<Ticket, PoolSendError<Req>> {
+        let env = env
+            .layered(self.layers.as_ref())
+            .recorded(self.recorder.as_ref());
+        submit(&self.send_req, &self.next_seq, &self.balancer, self.id, env)
+    }
+    /// Send a request to the pool
+    ///
+    /// Fails with [`PoolSendError::Stopped`] once the pool is stopped, or
+    /// [`PoolSendError::Unhandled`] when no runner taking new requests handles it's
+    /// [`kind`](ControlExecuteMessage::kind)
+    pub fn send(&self, req: Req) -> Result<Ticket, PoolSendError<Req>> {
+        self.submit(Envelope::new(req))
+    }
+    /// Send a request that's dispatched before this client's requests with a lower priority
+    pub fn send_with_priority(
+        &self,
+        req: Req,
+        priority: Priority,
+    ) -> Result<Ticket, PoolSendError<Req>> {
+        self.submit(Envelope::with_priority(req, priority))
+    }
+    /// Send a request that's skipped and answered with [`DeadlineExceeded`] if it's not executed before `deadline`
+    pub fn send_with_deadline(
+        &self,
+        req: Req,
+        deadline: Instant,
+    ) -> Result<Ticket, PoolSendError<Req>>
+    where
+        Ret<Req>: From<DeadlineExceeded>,
+    {
+        self.submit(Envelope::with_deadline(req, deadline))
+    }
+    /// Receive a response
+    ///
+    /// # Panics
+    ///
+    /// With the panic of the request, if it panicked
+    pub fn recv(&self) -> Result<Ret<Req>, RecvError> {
+        self.recv_tagged().map(|(_, res)| res)
+    }
+    /// Receive a response together with the sequence number of it's request's [`Ticket`]
+    ///
+    /// # Panics
+    ///
+    /// With the panic of the request, if it panicked
+    pub fn recv_tagged(&self) -> Result<(Seq, Ret<Req>), RecvError> {
+        let (seq, reply) = self.recv_res.recv()?;
+        Ok((seq, reply.unwrap()))
+    }
+}
+
+impl<Req> Drop for Client<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    fn drop(&mut self) {
+        let _ = self
+            .send_req
+            .send(ControlFlow::Continue(Command::Leave(self.id)));
+    }
+}
@@ -0,0 +1,138 @@
+use super::*;
+use std::time::Duration;
+
+/// Grows and shrinks a pool between `min` and `max` runners with it's load, see
+/// [`Pool::autoscale`]
+///
+/// The pool grows while requests wait in the dispatcher and at least [`Autoscale::scale_up_at`]
+/// of it's runners are busy, by up to as many runners as there are requests waiting but at most
+/// doubling. It shrinks one runner at a time once nothing waits and at most
+/// [`Autoscale::scale_down_at`] of it's runners stayed busy for the down cooldown
+#[derive(Debug, Clone, Copy)]
+pub struct Autoscale {
+    min: usize,
+    max: usize,
+    up_at: f64,
+    down_at: f64,
+    up_cooldown: Duration,
+    down_cooldown: Duration,
+}
+
+impl Autoscale {
+    pub fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        Self {
+            min,
+            max: max.max(min),
+            up_at: 1.0,
+            down_at: 0.5,
+            up_cooldown: Duration::from_millis(200),
+            down_cooldown: Duration::from_secs(5),
+        }
+    }
+    /// Fraction of busy runners from which a backlog grows the pool, all of them by default
+    ///
+    /// # Panics
+    ///
+    /// If `utilisation` isn't in `[0, 1]`
+    pub fn scale_up_at(mut self, utilisation: f64) -> Self {
+        check_utilisation(utilisation);
+        self.up_at = utilisation;
+        self
+    }
+    /// Fraction of busy runners up to which an idle dispatcher shrinks the pool, half by default
+    ///
+    /// # Panics
+    ///
+    /// If `utilisation` isn't in `[0, 1]`
+    pub fn scale_down_at(mut self, utilisation: f64) -> Self {
+        check_utilisation(utilisation);
+        self.down_at = utilisation;
+        self
+    }
+    /// Least time between the pool growing, and between it shrinking or it's load dropping and
+    /// it shrinking, 200ms and 5s by default
+    pub fn cooldowns(mut self, up: Duration, down: Duration) -> Self {
+        self.up_cooldown = up;
+        self.down_cooldown = down;
+        self
+    }
+    /// # Panics
+    ///
+    /// If the pool could shrink at a utilisation it grows at
+    pub(crate) fn check(&self) {
+        assert!(
+            self.down_at < self.up_at,
+            "autoscale must scale down below the utilisation it scales up at, got down at {} and \
+             up at {}",
+            self.down_at,
+            self.up_at
+        );
+    }
+}
+
+fn check_utilisation(utilisation: f64) {
+    assert!(
+        (0.0..=1.0).contains(&utilisation),
+        "utilisation must be in [0, 1], got {utilisation}"
+    );
+}
+
+/// Applies an [`Autoscale`] policy, owned by the pool's manager
+#[derive(Debug)]
+pub(crate) struct Autoscaler {
+    policy: Autoscale,
+    /// When the pool was last resized
+    changed: Instant,
+    /// Since when the pool's load is low enough to shrink it
+    low_since: Option<Instant>,
+}
+
+impl Autoscaler {
+    pub(crate) fn new(policy: Autoscale) -> Self {
+        Self {
+            policy,
+            changed: Instant::now(),
+            low_since: None,
+        }
+    }
+    /// The pool was resized by hand, which restarts the cooldowns
+    pub(crate) fn resized(&mut self) {
+        self.changed = Instant::now();
+        self.low_since = None;
+    }
+    /// How many runners the pool should have, when it's not `active` with `busy` of them and
+    /// `queued` requests waiting for one
+    pub(crate) fn target(&mut self, active: usize, busy: usize, queued: usize) -> Option<usize> {
+        let Autoscale { min, max, .. } = self.policy;
+        let now = Instant::now();
+        let target = if active < min || active > max {
+            active.clamp(min, max)
+        } else {
+            let utilisation = busy as f64 / active.max(1) as f64;
+            if queued > 0 && utilisation >= self.policy.up_at {
+                self.low_since = None;
+                if now.duration_since(self.changed) < self.policy.up_cooldown {
+                    return None;
+                }
+                (active + queued.min(active)).min(max)
+            } else if queued == 0 && utilisation <= self.policy.down_at {
+                let low_since = *self.low_since.get_or_insert(now);
+                let cooldown = self.policy.down_cooldown;
+                if now.duration_since(low_since) < cooldown
+                    || now.duration_since(self.changed) < cooldown
+                {
+                    return None;
+                }
+                active.saturating_sub(1).max(min)
+            } else {
+                self.low_since = None;
+                return None;
+            }
+        };
+        (target != active).then(|| {
+            self.changed = now;
+            target
+        })
+    }
+}
@@ -1,14 +1,26 @@
 use super::*;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
 
 #[derive(Debug)]
-pub struct PoolBalancer<const N: usize> {
+pub struct PoolBalancer {
     pub(crate) total: AtomicUsize,
-    runners: [PoolAnaliticRunner; N],
+    strategy: Strategy,
+    /// Runners taking new requests, the ones after them are retiring
+    active: AtomicUsize,
+    runners: RwLock<Vec<Arc<PoolAnaliticRunner>>>,
 }
 
 #[derive(Default, Debug)]
 struct PoolAnaliticRunner {
     running: AtomicUsize,
+    /// Summed [`ControlExecuteMessage::cost`] of the running requests
+    load: AtomicU64,
+    /// Average execution time per unit of cost in nanoseconds, 0 until a request finished
+    ewma: AtomicU64,
+    beat: Arc<Beat>,
+    spec: RunnerSpec,
 }
 
 pub(crate) struct PoolRunnerRef {
@@ -16,51 +28,266 @@ pub(crate) struct PoolRunnerRef {
     pub(crate) running: usize,
 }
 
-impl<const N: usize> PoolBalancer<N> {
-    fn get_by_id(&self, id: usize) -> &PoolAnaliticRunner {
-        &self.runners[id]
+impl PoolBalancer {
+    fn get_by_id(&self, id: usize) -> Arc<PoolAnaliticRunner> {
+        self.runners.read().unwrap()[id].clone()
     }
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(specs: Vec<RunnerSpec>, strategy: Strategy) -> Self {
         PoolBalancer {
             total: AtomicUsize::default(),
-            runners: [(); N].map(|_| PoolAnaliticRunner::default()),
+            strategy,
+            active: AtomicUsize::new(specs.len()),
+            runners: RwLock::new(
+                specs
+                    .into_iter()
+                    .map(|spec| {
+                        Arc::new(PoolAnaliticRunner {
+                            spec,
+                            ..Default::default()
+                        })
+                    })
+                    .collect(),
+            ),
         }
     }
-    #[must_use]
-    pub(crate) fn send(&self) -> PoolRunnerRef {
-        let mut min = PoolRunnerRef {
-            id: 0,
-            running: usize::MAX,
+    /// Runner taking new requests that would be done with a request of `cost` first, among
+    /// the ones `filter` accepts and that have less than their limit for `depth`
+    fn least_busy<F>(&self, depth: usize, cost: u64, filter: F) -> Option<PoolRunnerRef>
+    where
+        F: Fn(&RunnerSpec) -> bool,
+    {
+        // Compared as fractions, without rounding
+        let mut min: Option<(PoolRunnerRef, (u128, u128))> = None;
+        let runners = self.runners.read().unwrap();
+        let active = self.active().min(runners.len());
+        // Runners that never finished a request are expected to take as long as the others
+        let unmeasured = match self.strategy {
+            Strategy::LeastLoaded => 0,
+            Strategy::Latency { .. } => {
+                let (sum, measured) = runners[..active]
+                    .iter()
+                    .map(|runner| runner.ewma.load(std::sync::atomic::Ordering::Relaxed))
+                    .filter(|ewma| *ewma > 0)
+                    .fold((0, 0), |(sum, n), ewma| (sum + u128::from(ewma), n + 1));
+                sum.checked_div(measured).unwrap_or(1)
+            }
         };
-        for id in 0..N {
-            let running = self
-                .get_by_id(id)
-                .running
-                .load(std::sync::atomic::Ordering::SeqCst);
-            if running == 0 {
-                //eprintln!("[ACQ] Found idle runner #{id} (0 -> 1)");
-                self.get_by_id(id)
-                    .running
-                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                self.total
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                return PoolRunnerRef { id, running };
-            } else if running < min.running {
-                min = PoolRunnerRef { id, running };
+        for (id, runner) in runners[..active].iter().enumerate() {
+            // Stuck runners get no new requests
+            if !runner.beat.is_healthy() || !filter(&runner.spec) {
+                continue;
+            }
+            let running = runner.running.load(std::sync::atomic::Ordering::SeqCst);
+            if running >= runner.spec.limit(depth) {
+                continue;
+            }
+            let load = u128::from(runner.load.load(std::sync::atomic::Ordering::SeqCst) + cost);
+            let score = match self.strategy {
+                Strategy::LeastLoaded => (load, runner.spec.weight as u128),
+                Strategy::Latency { .. } => {
+                    let ewma = match runner.ewma.load(std::sync::atomic::Ordering::Relaxed) {
+                        0 => unmeasured,
+                        ewma => u128::from(ewma),
+                    };
+                    (load * ewma, 1)
+                }
+            };
+            if min
+                .as_ref()
+                .is_none_or(|(_, min)| score.0 * min.1 < min.0 * score.1)
+            {
+                min = Some((PoolRunnerRef { id, running }, score));
             }
         }
-        let _old = self
-            .get_by_id(min.id)
+        min.map(|(min, _)| min)
+    }
+    /// Whether any runner has room for a request at `depth`
+    pub(crate) fn has_room(&self, depth: usize) -> bool {
+        self.least_busy(depth, 1, |_| true).is_some()
+    }
+    /// Whether any runner taking new requests handles requests of `kind`, busy or not
+    pub(crate) fn handles(&self, kind: Option<u64>) -> bool {
+        let runners = self.runners.read().unwrap();
+        runners[..self.active().min(runners.len())]
+            .iter()
+            .any(|runner| runner.spec.handles(kind))
+    }
+    /// Fewest runners from `active` on whose first ones handle every kind the runners taking
+    /// new requests handle now
+    pub(crate) fn keeping_kinds(&self, active: usize) -> usize {
+        let runners = self.runners.read().unwrap();
+        let current = self.active().min(runners.len());
+        if active >= current {
+            return active;
+        }
+        let first = |handles: &dyn Fn(&RunnerSpec) -> bool| {
+            runners[..current]
+                .iter()
+                .position(|runner| handles(&runner.spec))
+        };
+        // A runner without kinds handles every kind
+        if let Some(any) = first(&|spec| spec.kinds.is_none()) {
+            return active.max(any + 1);
+        }
+        runners[active..current]
+            .iter()
+            .flat_map(|runner| runner.spec.kinds.iter().flatten())
+            .filter_map(|&kind| first(&|spec| spec.handles(Some(kind))))
+            .fold(active, |active, id| active.max(id + 1))
+    }
+    /// Pick the least busy runner handling `kind` for a request of `cost`, unless every such
+    /// runner is full at `depth`
+    #[must_use]
+    pub(crate) fn try_send(
+        &self,
+        depth: usize,
+        kind: Option<u64>,
+        cost: u64,
+    ) -> Option<PoolRunnerRef> {
+        let min = self.least_busy(depth, cost, |spec| spec.handles(kind))?;
+        let runner = self.get_by_id(min.id);
+        let _old = runner
             .running
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        runner
+            .load
+            .fetch_add(cost, std::sync::atomic::Ordering::SeqCst);
         self.total
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         //eprintln!("[ACQ] Best runner #{} ({} -> {})", min.id, _old, _old + 1);
-        min
+        Some(min)
+    }
+    /// Requests of each runner
+    pub(crate) fn running(&self) -> Vec<usize> {
+        self.runners
+            .read()
+            .unwrap()
+            .iter()
+            .map(|runner| runner.running.load(std::sync::atomic::Ordering::SeqCst))
+            .collect()
     }
-    pub(crate) fn done(&self, id: usize) {
-        let _old = self
-            .get_by_id(id)
+    /// Give a request of `cost` to runner `id`, whatever it's limits
+    pub(crate) fn assign(&self, id: usize, cost: u64) -> PoolRunnerRef {
+        let runner = self.get_by_id(id);
+        let running = runner
+            .running
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        runner
+            .load
+            .fetch_add(cost, std::sync::atomic::Ordering::SeqCst);
+        self.total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        PoolRunnerRef { id, running }
+    }
+    /// Summed cost of the requests of each runner
+    pub(crate) fn load(&self) -> Vec<u64> {
+        self.runners
+            .read()
+            .unwrap()
+            .iter()
+            .map(|runner| runner.load.load(std::sync::atomic::Ordering::SeqCst))
+            .collect()
+    }
+    /// Average execution time per unit of cost of each runner, `None` before it finished a
+    /// request
+    pub(crate) fn latency(&self) -> Vec<Option<Duration>> {
+        self.runners
+            .read()
+            .unwrap()
+            .iter()
+            .map(
+                |runner| match runner.ewma.load(std::sync::atomic::Ordering::Relaxed) {
+                    0 => None,
+                    nanos => Some(Duration::from_nanos(nanos)),
+                },
+            )
+            .collect()
+    }
+    /// Whether each runner is in service
+    pub(crate) fn healthy(&self) -> Vec<bool> {
+        self.runners
+            .read()
+            .unwrap()
+            .iter()
+            .map(|runner| runner.beat.is_healthy())
+            .collect()
+    }
+    /// Runners taking new requests that have some
+    pub(crate) fn busy(&self) -> usize {
+        let runners = self.runners.read().unwrap();
+        runners[..self.active().min(runners.len())]
+            .iter()
+            .filter(|runner| runner.running.load(std::sync::atomic::Ordering::SeqCst) > 0)
+            .count()
+    }
+    /// Beat of each runner
+    pub(crate) fn beats(&self) -> Vec<Arc<Beat>> {
+        self.runners
+            .read()
+            .unwrap()
+            .iter()
+            .map(|runner| runner.beat.clone())
+            .collect()
+    }
+    pub(crate) fn beat(&self, id: usize) -> Arc<Beat> {
+        self.get_by_id(id).beat.clone()
+    }
+    /// Runners taking new requests
+    pub(crate) fn active(&self) -> usize {
+        self.active.load(std::sync::atomic::Ordering::Acquire)
+    }
+    /// Take new requests with the first `active` runners only, the rest retire
+    pub(crate) fn set_active(&self, active: usize) {
+        self.active
+            .store(active, std::sync::atomic::Ordering::Release);
+    }
+    /// Add a runner taking what `spec` allows, returning it's beat
+    pub(crate) fn push(&self, spec: RunnerSpec) -> Arc<Beat> {
+        let runner = Arc::new(PoolAnaliticRunner {
+            spec,
+            ..Default::default()
+        });
+        let beat = runner.beat.clone();
+        self.runners.write().unwrap().push(runner);
+        beat
+    }
+    /// Remove the last runner, once it has no requests left
+    pub(crate) fn pop(&self) {
+        self.runners.write().unwrap().pop();
+    }
+    /// Whether the runner finished every request it was given
+    pub(crate) fn is_drained(&self, id: usize) -> bool {
+        self.get_by_id(id)
+            .running
+            .load(std::sync::atomic::Ordering::SeqCst)
+            == 0
+    }
+    /// Forget the requests of a runner that's replaced
+    pub(crate) fn reset(&self, id: usize) {
+        let runner = self.get_by_id(id);
+        runner.load.store(0, std::sync::atomic::Ordering::SeqCst);
+        let running = runner.running.swap(0, std::sync::atomic::Ordering::SeqCst);
+        self.total
+            .fetch_sub(running, std::sync::atomic::Ordering::Relaxed);
+    }
+    /// A request of `cost` finished on the runner after executing for `took`
+    pub(crate) fn done(&self, id: usize, cost: u64, took: Option<Duration>) {
+        let runner = self.get_by_id(id);
+        runner
+            .load
+            .fetch_sub(cost, std::sync::atomic::Ordering::SeqCst);
+        if let (Strategy::Latency { alpha }, Some(took)) = (self.strategy, took) {
+            let sample = took.as_nanos() as f64 / cost as f64;
+            let ewma = match runner.ewma.load(std::sync::atomic::Ordering::Relaxed) {
+                0 => sample,
+                ewma => alpha * sample + (1.0 - alpha) * ewma as f64,
+            };
+            // 0 means unmeasured
+            runner
+                .ewma
+                .store((ewma as u64).max(1), std::sync::atomic::Ordering::Relaxed);
+        }
+        let _old = runner
             .running
             .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
         self.total
@@ -1,7 +1,12 @@
 use super::*;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
 
-pub type PoolCloseRecvPair<Req, const N: usize> =
-    (PoolCloser<Req, N, ReceiverReturned>, Receiver<Ret<Req>>);
+/// How long closing waits for a response before checking the dispatcher again
+const CLOSE_POLL: Duration = Duration::from_millis(1);
+
+pub type PoolCloseRecvPair<Req> =
+    (PoolCloser<Req, ReceiverReturned>, Receiver<Ret<Req>>);
 
 pub trait PoolCloserMarker {}
 pub struct ReceiverDropped;
@@ -11,103 +16,189 @@ impl PoolCloserMarker for ReceiverReturned {}
 
 #[derive(Debug)]
 #[must_use]
-pub struct PoolCloser<Req, const N: usize, R>
+pub struct PoolCloser<Req, R>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
     R: PoolCloserMarker,
 {
-    recv_pooled_response: Receiver<Pooled<Ret<Req>>>,
-    runners: [PoolCon<Req>; N],
-    balancer: PoolBalancer<N>,
-    user_send_response: Sender<Ret<Req>>,
+    manager: Manager<Req>,
+    /// Feeds the receiver returned by [`PoolApi::stop`]
+    returned: Option<Sender<Ret<Req>>>,
+    /// First panic of a request whose response was due, resumed once the pool is closed
+    panic: Option<Box<dyn std::any::Any + Send>>,
     _mark: PhantomData<R>,
 }
 
-pub struct PoolCloserDef<Req, const N: usize>
+pub struct PoolCloserDef<Req>
 where
     Req: ControlExecuteMessage,
 {
-    pub recv_pooled_response: Receiver<Pooled<Ret<Req>>>,
-    pub runners: [PoolCon<Req>; N],
-    pub balancer: PoolBalancer<N>,
-    pub user_send_response: Sender<Ret<Req>>,
+    pub(crate) manager: Manager<Req>,
 }
 
-impl<Req, const N: usize, R> From<PoolCloserDef<Req, N>> for PoolCloser<Req, N, R>
+impl<Req, R> From<PoolCloserDef<Req>> for PoolCloser<Req, R>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
     R: PoolCloserMarker,
 {
-    fn from(value: PoolCloserDef<Req, N>) -> Self {
+    fn from(value: PoolCloserDef<Req>) -> Self {
         Self {
-            recv_pooled_response: value.recv_pooled_response,
-            runners: value.runners,
-            balancer: value.balancer,
-            user_send_response: value.user_send_response,
+            manager: value.manager,
+            returned: None,
+            panic: None,
             _mark: PhantomData,
         }
     }
 }
 
-impl<Req, const N: usize, R> PoolCloser<Req, N, R>
+impl<Req, R> PoolCloser<Req, R>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
     R: PoolCloserMarker,
 {
-    fn kill<S>(self, closer: &S)
+    /// Send a response to the receiver returned by [`PoolApi::stop`], or keep the panic of a
+    /// request for later
+    pub(crate) fn hand_back(&mut self, reply: Reply<Ret<Req>>) {
+        match reply {
+            Reply::Response(response) => {
+                if let Some(returned) = &self.returned {
+                    // The receiver may be gone
+                    let _ = returned.send(response);
+                }
+            }
+            Reply::Panicked(panic) => {
+                self.panic.get_or_insert(panic);
+            }
+        }
+    }
+
+    /// Stop every runner, then resume the first panic of a request whose response was due
+    fn kill<S>(mut self, closer: &S)
     where
         S: StopRunner<Req>,
     {
-        for (runner_id, runner) in self.runners.into_iter().enumerate() {
-            runner.send(Pooled::pack(runner_id, closer.get())).unwrap();
+        let panic = self.panic.take();
+        let phase = Phase::kill(self.manager.runners.len());
+        for (runner_id, runner) in self.manager.runners.into_iter().enumerate() {
+            runner
+                .send(Pooled::pack(
+                    runner_id,
+                    0,
+                    Envelope::stop(closer.get()),
+                    RequestSpan::default(),
+                ))
+                .unwrap();
             runner._thread.join().unwrap();
+            phase.runner_stopped(runner_id);
+        }
+        // Stuck runners stop if they ever return, nobody waits for them
+        for runner in self.manager.replaced {
+            let _ = runner.send(Pooled::pack(
+                0,
+                0,
+                Envelope::stop(closer.get()),
+                RequestSpan::default(),
+            ));
+        }
+        if let Some(panic) = panic {
+            std::panic::resume_unwind(panic);
         }
     }
 
-    fn await_runners<F>(&self, mut f: F)
+    fn await_runners<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Seq, Reply<Ret<Req>>),
+    {
+        let _phase = Phase::close(
+            self.manager.queued(),
+            self.manager
+                .balancer
+                .total
+                .load(std::sync::atomic::Ordering::Relaxed),
+        );
+        // Closing executes every queued request, even of a paused pool
+        self.manager.stats.set_paused(false);
+        loop {
+            self.manager.dispatch();
+            if self.manager.is_idle() {
+                return;
+            }
+            // Requests held back by the rate limiter may be all that's left
+            match self
+                .manager
+                .recv_pooled_response
+                .recv_timeout(CLOSE_POLL)
+            {
+                Ok(pooled_response) => self.manager.complete(pooled_response, &mut f),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => panic!("Channel closed"),
+            }
+        }
+    }
+    /// Await every executor finish their tasks, passing their responses to `f` and keeping the
+    /// first panic for later
+    fn await_responses<F>(&mut self, mut f: F)
     where
         F: FnMut(Ret<Req>),
     {
-        for _ in 0..self
-            .balancer
-            .total
-            .load(std::sync::atomic::Ordering::Relaxed)
-        {
-            let pooled_response = self.recv_pooled_response.recv().unwrap();
-            let (runner_id, response) = pooled_response.unpack();
-            self.balancer.done(runner_id);
-            f(response)
+        let mut panic = None;
+        self.await_runners(|_, reply| match reply {
+            Reply::Response(response) => f(response),
+            Reply::Panicked(first) => {
+                panic.get_or_insert(first);
+            }
+        });
+        if let Some(panic) = panic {
+            self.panic.get_or_insert(panic);
         }
     }
+
+    /// Await every executor finish their tasks and send their responses tagged to the pool's
+    /// response channel, for whoever took it with [`PoolApi::take_responses`]
+    pub(crate) fn close_tagged<S>(mut self, closer: &S)
+    where
+        S: StopRunner<Req>,
+    {
+        let user_send_response = self.manager.user_send_response.clone();
+        self.await_runners(|seq, reply| {
+            // The receiver may be gone
+            let _ = user_send_response.send((seq, reply));
+        });
+        self.kill(closer);
+    }
     #[must_use]
-    fn _close_capture<S>(self, closer: &S) -> Vec<Ret<Req>>
+    fn _close_capture<S>(mut self, closer: &S) -> Vec<std::thread::Result<Ret<Req>>>
     where
         S: StopRunner<Req>,
     {
         let mut late = Vec::with_capacity(
-            self.balancer
-                .total
-                .load(std::sync::atomic::Ordering::Relaxed),
+            self.manager.queued()
+                + self
+                    .manager
+                    .balancer
+                    .total
+                    .load(std::sync::atomic::Ordering::Relaxed),
         );
-        self.await_runners(|response| {
-            late.push(response);
-        });
+        // Captured instead of resumed once the runners stopped
+        late.extend(self.panic.take().map(Err));
+        self.await_runners(|_, reply| late.push(reply.into_result()));
         self.kill(closer);
         late
     }
 }
 
-impl<Req, const N: usize> PoolCloser<Req, N, ReceiverDropped>
+impl<Req> PoolCloser<Req, ReceiverDropped>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
-    /// Await every executor finish their tasks and capture their responses
+    /// Await every executor finish their tasks and capture their responses, `Err` with the
+    /// panic of the requests that panicked
     #[must_use]
-    pub fn close_capture<S>(self, closer: &S) -> Vec<Ret<Req>>
+    pub fn close_capture<S>(self, closer: &S) -> Vec<std::thread::Result<Ret<Req>>>
     where
         S: StopRunner<Req>,
     {
@@ -115,24 +206,43 @@ where
     }
 }
 
-impl<Req, const N: usize> PoolCloser<Req, N, ReceiverReturned>
+impl<Req> PoolCloser<Req, ReceiverReturned>
 where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
+    pub(crate) fn returning(def: PoolCloserDef<Req>, returned: Sender<Ret<Req>>) -> Self {
+        Self {
+            returned: Some(returned),
+            ..Self::from(def)
+        }
+    }
     /// Await every executor finish their tasks and send their responses
-    pub fn close_await<S>(self, closer: &S)
+    ///
+    /// # Panics
+    ///
+    /// With the first panic of a request, once every runner stopped
+    pub fn close_await<S>(mut self, closer: &S)
     where
         S: StopRunner<Req>,
     {
-        self.await_runners(|response| {
-            self.user_send_response.send(response).unwrap();
+        let returned = self.returned.clone();
+        self.await_responses(|response| {
+            if let Some(returned) = &returned {
+                // The receiver may be gone
+                let _ = returned.send(response);
+            }
         });
         self.kill(closer);
     }
-    /// Await every executor finish their tasks and capture their responses
+    /// Await every executor finish their tasks and capture their responses, `Err` with the
+    /// panic of the requests that panicked
     #[must_use]
-    pub fn close_capture<S>(self, closer: &S, _: Receiver<Ret<Req>>) -> Vec<Ret<Req>>
+    pub fn close_capture<S>(
+        self,
+        closer: &S,
+        _: Receiver<Ret<Req>>,
+    ) -> Vec<std::thread::Result<Ret<Req>>>
     where
         S: StopRunner<Req>,
     {
@@ -0,0 +1,154 @@
+use super::*;
+use std::collections::HashMap;
+
+/// Identifies who submitted a request to a pool, the [`PoolApi`] itself is [`DEFAULT_CLIENT`]
+pub type ClientId = usize;
+
+/// Client of requests sent through the [`PoolApi`] itself
+pub const DEFAULT_CLIENT: ClientId = 0;
+
+/// How much of a pool a [`Client`] gets
+///
+/// Clients with queued requests take turns, each one dispatching up to `weight` requests per
+/// turn, and none having more than `max_in_flight` requests dispatched at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Share {
+    weight: u32,
+    max_in_flight: Option<usize>,
+}
+
+impl Share {
+    pub fn new(weight: u32) -> Self {
+        Self {
+            weight: weight.max(1),
+            max_in_flight: None,
+        }
+    }
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight.max(1));
+        self
+    }
+}
+
+impl Default for Share {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[derive(Debug)]
+struct ClientQueue<T> {
+    share: Share,
+    lanes: Lanes<T>,
+    /// Requests left in the client's current turn
+    deficit: u32,
+    /// Requests taken from the queue that didn't finish yet
+    in_flight: usize,
+    left: bool,
+}
+
+impl<T> ClientQueue<T> {
+    fn new(share: Share) -> Self {
+        Self {
+            share,
+            lanes: Lanes::new(),
+            deficit: 0,
+            in_flight: 0,
+            left: false,
+        }
+    }
+    fn is_capped(&self) -> bool {
+        self.share
+            .max_in_flight
+            .is_some_and(|max| self.in_flight >= max)
+    }
+}
+
+/// Per client queues of requests, served by deficit round robin
+#[derive(Debug)]
+pub(crate) struct Fair<T> {
+    clients: HashMap<ClientId, ClientQueue<T>>,
+    /// Clients with queued requests, the first one is taking it's turn
+    active: VecDeque<ClientId>,
+    len: usize,
+}
+
+impl<T> Fair<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            clients: HashMap::new(),
+            active: VecDeque::new(),
+            len: 0,
+        }
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub(crate) fn join(&mut self, client: ClientId, share: Share) {
+        self.clients
+            .entry(client)
+            .or_insert_with(|| ClientQueue::new(share))
+            .share = share;
+    }
+    /// Forget the client once it's queued requests are taken
+    pub(crate) fn leave(&mut self, client: ClientId) {
+        if let Some(queue) = self.clients.get_mut(&client) {
+            queue.left = true;
+            if queue.lanes.is_empty() {
+                self.clients.remove(&client);
+            }
+        }
+    }
+    pub(crate) fn push(&mut self, client: ClientId, priority: Priority, v: T) {
+        let queue = self
+            .clients
+            .entry(client)
+            .or_insert_with(|| ClientQueue::new(Share::default()));
+        if queue.lanes.is_empty() {
+            self.active.push_back(client);
+        }
+        queue.lanes.push(priority, v);
+        self.len += 1;
+    }
+    /// Take the next request of the client whose turn it is, skipping clients at their cap
+    ///
+    /// The request counts as in flight for it's client until [`Fair::done`]
+    pub(crate) fn pop(&mut self) -> Option<(ClientId, T)> {
+        for _ in 0..self.active.len() {
+            let client = *self.active.front()?;
+            let queue = self.clients.get_mut(&client)?;
+            if queue.is_capped() {
+                queue.deficit = 0;
+                self.active.rotate_left(1);
+                continue;
+            }
+            if queue.deficit == 0 {
+                queue.deficit = queue.share.weight;
+            }
+            queue.deficit -= 1;
+            let v = queue.lanes.pop()?;
+            queue.in_flight += 1;
+            self.len -= 1;
+            if queue.lanes.is_empty() {
+                queue.deficit = 0;
+                self.active.pop_front();
+                if queue.left {
+                    self.clients.remove(&client);
+                }
+            } else if queue.deficit == 0 {
+                self.active.rotate_left(1);
+            }
+            return Some((client, v));
+        }
+        None
+    }
+    /// A request taken from `client`'s queue finished or was dropped
+    pub(crate) fn done(&mut self, client: ClientId) {
+        if let Some(queue) = self.clients.get_mut(&client) {
+            queue.in_flight = queue.in_flight.saturating_sub(1);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use super::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// In flight requests allowed per pool runner when [`Map::window`] isn't set
+const DEFAULT_WINDOW_PER_RUNNER: usize = 2;
+
+/// Iterator mapping requests over a [`PoolApi`], made by [`PoolApi::map`] and [`PoolApi::map_unordered`]
+///
+/// Dropping it early cancels the requests still in flight, waits for the ones already running
+/// and discards their responses
+///
+/// # Panics
+///
+/// Yielding the response of a request that panicked resumes it's panic, sending a request the
+/// pool doesn't take panics with the [`PoolSendError`]
+#[must_use]
+pub struct Map<'a, Req, I>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+    I: Iterator<Item = Req>,
+{
+    pool: &'a mut PoolApi<Req>,
+    reqs: I,
+    window: usize,
+    ordered: bool,
+    /// Input index and ticket of every request sent but not yet answered
+    in_flight: HashMap<Seq, (usize, Ticket)>,
+    /// Answered requests waiting for an earlier one, when ordered
+    done: BTreeMap<usize, Reply<Ret<Req>>>,
+    sent: usize,
+    yielded: usize,
+}
+
+impl<Req, I> Map<'_, Req, I>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+    I: Iterator<Item = Req>,
+{
+    /// Limit how many requests may be sent and not yet yielded at once
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    fn fill(&mut self) {
+        while self.sent - self.yielded < self.window {
+            let Some(req) = self.reqs.next() else {
+                break;
+            };
+            let ticket = self.pool.send(req).unwrap_or_else(|e| panic!("{e}"));
+            self.in_flight.insert(ticket.seq(), (self.sent, ticket));
+            self.sent += 1;
+        }
+    }
+
+    /// Receive the next response of this adapter, stashing the ones sent by someone else
+    fn recv_own(&mut self) -> (usize, Reply<Ret<Req>>) {
+        loop {
+            let (seq, res) = self.pool.recv_res.recv().expect("pool manager stopped");
+            match self.in_flight.remove(&seq) {
+                Some((idx, _)) => return (idx, res),
+                None => self.pool.stash.borrow_mut().push_back((seq, res)),
+            }
+        }
+    }
+}
+
+impl<Req, I> Iterator for Map<'_, Req, I>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+    I: Iterator<Item = Req>,
+{
+    type Item = Ret<Req>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(res) = self.done.remove(&self.yielded) {
+                self.yielded += 1;
+                return Some(res.unwrap());
+            }
+            self.fill();
+            if self.in_flight.is_empty() {
+                return None;
+            }
+            let (idx, res) = self.recv_own();
+            if !self.ordered || idx == self.yielded {
+                self.yielded += 1;
+                return Some(res.unwrap());
+            }
+            self.done.insert(idx, res);
+        }
+    }
+}
+
+impl<Req, I> Drop for Map<'_, Req, I>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+    I: Iterator<Item = Req>,
+{
+    fn drop(&mut self) {
+        // Requests removed before they started get no response
+        self.in_flight
+            .retain(|_, (_, ticket)| ticket.cancel() != Cancellation::Dequeued);
+        while !self.in_flight.is_empty() {
+            self.recv_own();
+        }
+    }
+}
+
+impl<Req> PoolApi<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    fn make_map<I>(&mut self, reqs: I, ordered: bool) -> Map<'_, Req, I::IntoIter>
+    where
+        I: IntoIterator<Item = Req>,
+    {
+        let window = (self.balancer.active() * DEFAULT_WINDOW_PER_RUNNER).max(1);
+        Map {
+            pool: self,
+            reqs: reqs.into_iter(),
+            window,
+            ordered,
+            in_flight: HashMap::new(),
+            done: BTreeMap::new(),
+            sent: 0,
+            yielded: 0,
+        }
+    }
+
+    /// Execute every request on the pool, yielding responses in the order of `reqs`
+    pub fn map<I>(&mut self, reqs: I) -> Map<'_, Req, I::IntoIter>
+    where
+        I: IntoIterator<Item = Req>,
+    {
+        self.make_map(reqs, true)
+    }
+
+    /// Execute every request on the pool, yielding responses as soon as they're done
+    pub fn map_unordered<I>(&mut self, reqs: I) -> Map<'_, Req, I::IntoIter>
+    where
+        I: IntoIterator<Item = Req>,
+    {
+        self.make_map(reqs, false)
+    }
+
+    /// Execute every request on the pool, calling `f` with each response as soon as it's done
+    pub fn for_each<I, F>(&mut self, reqs: I, f: F)
+    where
+        I: IntoIterator<Item = Req>,
+        F: FnMut(Ret<Req>),
+    {
+        self.map_unordered(reqs).for_each(f);
+    }
+
+    /// Execute every request on the pool, collecting the responses in the order of `reqs`
+    ///
+    /// Stops sending requests on the first error, waits for the ones in flight and returns it
+    pub fn try_map<I, T, E>(&mut self, reqs: I) -> Result<Vec<T>, E>
+    where
+        I: IntoIterator<Item = Req>,
+        Req: ControlExecuteMessage<Res = Result<T, E>>,
+        T: 'static,
+        E: 'static,
+    {
+        self.map(reqs).collect()
+    }
+
+    /// Execute every request on the pool, folding the responses with `f` as soon as they're done
+    pub fn reduce<I, F>(&mut self, reqs: I, f: F) -> Option<Ret<Req>>
+    where
+        I: IntoIterator<Item = Req>,
+        F: FnMut(Ret<Req>, Ret<Req>) -> Ret<Req>,
+    {
+        self.map_unordered(reqs).reduce(f)
+    }
+}
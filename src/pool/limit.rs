@@ -0,0 +1,182 @@
+use super::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+/// Keyed buckets kept around before the full ones are forgotten
+const KEYED_BUCKETS_PRUNE: usize = 1024;
+
+/// Token bucket settings, `per_second` tokens are refilled up to `burst` tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    per_second: f64,
+    burst: f64,
+}
+
+impl Rate {
+    /// # Panics
+    ///
+    /// If `per_second` isn't a positive number
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        assert!(
+            per_second.is_finite() && per_second > 0.0,
+            "rate must be a positive number of requests per second, got {per_second}"
+        );
+        Self {
+            per_second,
+            burst: f64::from(burst.max(1)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: Rate, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate.burst,
+            last: now,
+        }
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate.per_second).min(self.rate.burst);
+        self.last = now;
+    }
+    fn is_full(&self) -> bool {
+        self.tokens >= self.rate.burst
+    }
+    fn ready(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= 1.0
+    }
+    fn try_take(&mut self, now: Instant) -> bool {
+        let ready = self.ready(now);
+        if ready {
+            self.tokens -= 1.0;
+        }
+        ready
+    }
+}
+
+/// What the limiter needs to know of the requests it delays
+pub(crate) trait Limited {
+    fn key(&self) -> Option<u64>;
+    fn priority(&self) -> Priority;
+    fn is_cancelled(&self) -> bool;
+}
+
+/// Delays requests until both the global bucket and their key's bucket have a token
+#[derive(Debug)]
+pub(crate) struct Limiter<T> {
+    global: Option<TokenBucket>,
+    per_key: Option<Rate>,
+    keyed: HashMap<u64, TokenBucket>,
+    /// Requests waiting for their key's bucket with their key, highest priority first and in
+    /// the order they were delayed on ties
+    delayed: BTreeMap<(Reverse<Priority>, u64), (u64, T)>,
+    /// Requests delayed so far, used to keep their order
+    delays: u64,
+}
+
+impl<T> Limiter<T>
+where
+    T: Limited,
+{
+    pub(crate) fn new(global: Option<Rate>, per_key: Option<Rate>) -> Self {
+        let now = Instant::now();
+        Self {
+            global: global.map(|rate| TokenBucket::new(rate, now)),
+            per_key,
+            keyed: HashMap::new(),
+            delayed: BTreeMap::new(),
+            delays: 0,
+        }
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.delayed.len()
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.delayed.is_empty()
+    }
+
+    fn take_keyed(&mut self, key: u64, now: Instant) -> bool {
+        let Some(rate) = self.per_key else {
+            return true;
+        };
+        if self.keyed.len() > KEYED_BUCKETS_PRUNE {
+            let delayed: HashSet<_> = self.delayed.values().map(|(key, _)| *key).collect();
+            self.keyed.retain(|key, bucket| {
+                bucket.refill(now);
+                !bucket.is_full() || delayed.contains(key)
+            });
+        }
+        self.keyed
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(rate, now))
+            .try_take(now)
+    }
+
+    /// Highest priority delayed request whose key's bucket has a token, moving the cancelled
+    /// ones passed on the way to `cancelled` without taking a token for them
+    fn take_delayed(&mut self, now: Instant, cancelled: &mut Vec<T>) -> Option<T> {
+        let mut passed = Vec::new();
+        let mut found = None;
+        for (slot, (key, v)) in &self.delayed {
+            if v.is_cancelled() {
+                passed.push(*slot);
+            } else if self
+                .keyed
+                .get_mut(key)
+                .is_none_or(|bucket| bucket.ready(now))
+            {
+                found = Some((*slot, *key));
+                break;
+            }
+        }
+        for slot in passed {
+            cancelled.extend(self.delayed.remove(&slot).map(|(_, v)| v));
+        }
+        let (slot, key) = found?;
+        self.take_keyed(key, now);
+        self.delayed.remove(&slot).map(|(_, v)| v)
+    }
+
+    /// Next request allowed to run, taken from the delayed ones first and then from `pop`
+    ///
+    /// Cancelled requests found among the delayed ones are moved to `cancelled`
+    pub(crate) fn next<P>(&mut self, mut pop: P, cancelled: &mut Vec<T>) -> Option<T>
+    where
+        P: FnMut() -> Option<T>,
+    {
+        let now = Instant::now();
+        if let Some(global) = &mut self.global
+            && !global.ready(now)
+        {
+            return None;
+        }
+        let v = match self.take_delayed(now, cancelled) {
+            Some(v) => v,
+            None => loop {
+                let v = pop()?;
+                match v.key() {
+                    Some(key) if !self.take_keyed(key, now) => {
+                        self.delayed
+                            .insert((Reverse(v.priority()), self.delays), (key, v));
+                        self.delays += 1;
+                    }
+                    _ => break v,
+                }
+            },
+        };
+        if let Some(global) = &mut self.global {
+            global.try_take(now);
+        }
+        Some(v)
+    }
+}
@@ -0,0 +1,540 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+/// Holds back responses until every request dispatched before them is done
+#[derive(Debug)]
+pub(crate) struct Reorder<T> {
+    bound: usize,
+    /// Sequence numbers in dispatch order that weren't released yet
+    pending: VecDeque<Seq>,
+    /// `None` for requests cancelled after being dispatched
+    done: HashMap<Seq, Option<T>>,
+}
+
+impl<T> Reorder<T> {
+    pub(crate) fn new(bound: usize) -> Self {
+        Self {
+            bound: bound.max(1),
+            pending: VecDeque::new(),
+            done: HashMap::new(),
+        }
+    }
+    fn is_full(&self) -> bool {
+        self.pending.len() >= self.bound
+    }
+    fn dispatched(&mut self, seq: Seq) {
+        self.pending.push_back(seq);
+    }
+    fn complete<F>(&mut self, seq: Seq, v: Option<T>, mut f: F)
+    where
+        F: FnMut(Seq, T),
+    {
+        self.done.insert(seq, v);
+        while let Some(head) = self.pending.front()
+            && let Some(v) = self.done.remove(head)
+        {
+            if let Some(v) = v {
+                f(*head, v);
+            }
+            self.pending.pop_front();
+        }
+    }
+}
+
+/// Hand a response to it's client, or to `f` for [`DEFAULT_CLIENT`]
+fn deliver<Res, F>(
+    clients: &HashMap<ClientId, Sender<(Seq, Reply<Res>)>>,
+    f: &mut F,
+    seq: Seq,
+    (client, response): (ClientId, Reply<Res>),
+) where
+    F: FnMut(Seq, Reply<Res>),
+{
+    match clients.get(&client) {
+        // The client may be gone
+        Some(send) => {
+            let _ = send.send((seq, response));
+        }
+        None if client == DEFAULT_CLIENT => f(seq, response),
+        None => {}
+    }
+}
+
+impl<Req> Limited for (ClientId, Seq, Envelope<Req>)
+where
+    Req: ControlExecuteMessage,
+{
+    fn key(&self) -> Option<u64> {
+        self.2.key()
+    }
+    fn priority(&self) -> Priority {
+        self.2.priority()
+    }
+    fn is_cancelled(&self) -> bool {
+        self.2.is_cancelled()
+    }
+}
+
+/// Broadcast sent while the pool was paused, with the channel of it's responses
+pub(crate) type HeldBroadcast<Req> = (MakeReq<Req>, Sender<Gathered<Ret<Req>>>);
+
+/// Responses of a broadcast gathered so far, by runner
+#[derive(Debug)]
+pub(crate) struct Gather<T> {
+    responses: Vec<Option<T>>,
+    left: usize,
+    send: Sender<Gathered<T>>,
+}
+
+/// State owned by the pool's manager thread, handed to the [`PoolCloser`] when the pool stops
+pub(crate) struct Manager<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    /// Runners taking requests followed by the retiring ones, see [`PoolBalancer::active`]
+    pub(crate) runners: Vec<PoolCon<Req>>,
+    pub(crate) balancer: Arc<PoolBalancer>,
+    pub(crate) recv_pooled_response: Receiver<Pooled<Executed<Option<Ret<Req>>>>>,
+    pub(crate) user_send_response: Sender<Tagged<Req>>,
+    pub(crate) reorder: Option<Reorder<(ClientId, Reply<Ret<Req>>)>>,
+    /// Requests waiting for a runner with less than `depth` requests queued
+    pub(crate) backlog: Fair<(Seq, Envelope<Req>)>,
+    /// Requests taken from the backlog that no runner handling their kind had room for
+    pub(crate) parked: VecDeque<(ClientId, Seq, Envelope<Req>)>,
+    /// Client, send time, runner and cost of every request taken from the backlog that wasn't
+    /// answered yet
+    pub(crate) owners: HashMap<Seq, (ClientId, Instant, usize, u64)>,
+    /// Response channels of the clients besides [`DEFAULT_CLIENT`]
+    pub(crate) clients: HashMap<ClientId, Sender<Tagged<Req>>>,
+    pub(crate) depth: usize,
+    pub(crate) limiter: Option<Limiter<(ClientId, Seq, Envelope<Req>)>>,
+    pub(crate) stats: Arc<Stats>,
+    pub(crate) hooks: Option<Box<dyn PoolHooks>>,
+    /// Given to the runners started in place of stuck ones
+    pub(crate) send_pooled_response: Sender<Pooled<Executed<Option<Ret<Req>>>>>,
+    pub(crate) batching: Option<Batching>,
+    pub(crate) spawner: Spawner,
+    /// Stuck runners that were replaced
+    pub(crate) replaced: Vec<PoolCon<Req>>,
+    /// Requests of the replaced runners, no longer counted by the balancer
+    pub(crate) orphaned: HashSet<Seq>,
+    pub(crate) autoscaler: Option<Autoscaler>,
+    /// Shared with the pool's api, for the requests of broadcasts
+    pub(crate) next_seq: Arc<AtomicUsize>,
+    pub(crate) layers: Option<Arc<Layers<Req>>>,
+    pub(crate) recorder: Option<Recorder>,
+    /// Broadcast and runner of every broadcast request that wasn't answered yet
+    pub(crate) broadcast_of: HashMap<Seq, (Seq, usize)>,
+    /// Broadcasts waiting for responses, by the sequence number of their first request
+    pub(crate) gathers: HashMap<Seq, Gather<Ret<Req>>>,
+    /// Broadcasts waiting for the pool to be resumed
+    pub(crate) broadcasts: VecDeque<HeldBroadcast<Req>>,
+    /// Stops the watchdog when the manager is dropped
+    pub(crate) _watchdog: Option<Sender<()>>,
+}
+
+impl<Req> std::fmt::Debug for Manager<Req>
+where
+    Req: ControlExecuteMessage + std::fmt::Debug,
+    Ret<Req>: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Manager")
+            .field("runners", &self.runners)
+            .field("balancer", &self.balancer)
+            .field("reorder", &self.reorder)
+            .field("backlog", &self.backlog)
+            .field("parked", &self.parked)
+            .field("owners", &self.owners)
+            .field("depth", &self.depth)
+            .field("limiter", &self.limiter)
+            .field("autoscaler", &self.autoscaler)
+            .field("gathers", &self.gathers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Req> Manager<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    /// Next request that's not cancelled and within the rate limits
+    fn next_ready(&mut self) -> Option<(ClientId, Seq, Envelope<Req>)> {
+        let backlog = &mut self.backlog;
+        let mut pop = || loop {
+            let (client, (seq, env)) = backlog.pop()?;
+            if !env.is_cancelled() {
+                return Some((client, seq, env));
+            }
+            backlog.done(client);
+        };
+        let Some(limiter) = &mut self.limiter else {
+            return pop();
+        };
+        let mut cancelled = Vec::new();
+        let next = limiter.next(pop, &mut cancelled);
+        for (client, _, _) in cancelled {
+            self.backlog.done(client);
+        }
+        next
+    }
+
+    /// Hand backlogged requests to runners, taking turns between clients and the highest
+    /// priority first within a client, while any runner has room
+    ///
+    /// Unless the pool is paused, waiting broadcasts go out first
+    ///
+    /// Requests whose runners are all full are parked and tried first next time. Requests no
+    /// runner handles are rejected when sent and resizing keeps the runners of the kinds
+    /// handled, the ones left anyway are dropped like cancelled ones
+    pub(crate) fn dispatch(&mut self) {
+        if !self.stats.is_paused() {
+            while let Some((make_req, send)) = self.broadcasts.pop_front() {
+                self.broadcast(make_req, send);
+            }
+        }
+        let mut waiting = VecDeque::new();
+        while !self.stats.is_paused()
+            && !self.reorder.as_ref().is_some_and(Reorder::is_full)
+            && self.balancer.has_room(self.depth)
+        {
+            let next = match self.parked.pop_front() {
+                Some((client, _, env)) if env.is_cancelled() => {
+                    self.backlog.done(client);
+                    continue;
+                }
+                Some(parked) => Some(parked),
+                None => self.next_ready(),
+            };
+            let Some((client, seq, env)) = next else {
+                break;
+            };
+            let cost = env.cost().max(1);
+            let Some(runner_ref) = self.balancer.try_send(self.depth, env.kind(), cost) else {
+                if self.balancer.handles(env.kind()) {
+                    waiting.push_back((client, seq, env));
+                } else {
+                    self.backlog.done(client);
+                }
+                continue;
+            };
+            self.owners
+                .insert(seq, (client, env.enqueued(), runner_ref.id, cost));
+            self.send_to(&runner_ref, seq, env);
+            if let Some(reorder) = &mut self.reorder {
+                reorder.dispatched(seq);
+            }
+        }
+        // Parked requests keep their turn ahead of the ones parked now
+        waiting.extend(self.parked.drain(..));
+        self.parked = waiting;
+        self.stats.set_queued(
+            self.backlog.len() + self.parked.len(),
+            self.limiter.as_ref().map_or(0, Limiter::len),
+        );
+    }
+
+    /// Hand a request the balancer assigned to it's runner
+    fn send_to(&self, runner_ref: &PoolRunnerRef, seq: Seq, mut env: Envelope<Req>) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_dispatch(seq, runner_ref.id);
+        }
+        env.span().dispatched(runner_ref.id, runner_ref.running);
+        env.dispatched();
+        let span = env.take_span();
+        let pooled_req = Pooled::pack(runner_ref.id, seq, env, span);
+        self.runners[runner_ref.id].send(pooled_req).unwrap();
+    }
+
+    /// Send the request `make_req` makes for each runner taking requests to it, gathering the
+    /// responses for `send`
+    ///
+    /// A panicking `make_req` fails the broadcast, not the manager
+    fn broadcast(&mut self, mut make_req: MakeReq<Req>, send: Sender<Gathered<Ret<Req>>>) {
+        let runners = self.balancer.active();
+        let reqs = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            (0..runners).map(&mut make_req).collect::<Vec<_>>()
+        }));
+        let reqs = match reqs {
+            Ok(reqs) => reqs,
+            Err(panic) => {
+                // The caller may be gone
+                let _ = send.send(Err(panic));
+                return;
+            }
+        };
+        let first = self
+            .next_seq
+            .fetch_add(runners, std::sync::atomic::Ordering::Relaxed);
+        for (runner, req) in reqs.into_iter().enumerate() {
+            let seq = first + runner;
+            let env = Envelope::new(req)
+                .layered(self.layers.as_ref())
+                .recorded(self.recorder.as_ref())
+                .traced(RequestSpan::new(seq, DEFAULT_CLIENT));
+            let cost = env.cost().max(1);
+            let runner_ref = self.balancer.assign(runner, cost);
+            self.owners
+                .insert(seq, (DEFAULT_CLIENT, env.enqueued(), runner, cost));
+            self.broadcast_of.insert(seq, (first, runner));
+            self.send_to(&runner_ref, seq, env);
+        }
+        self.gathers.insert(
+            first,
+            Gather {
+                responses: (0..runners).map(|_| None).collect(),
+                left: runners,
+                send,
+            },
+        );
+    }
+
+    /// Requests given to the manager that weren't dispatched yet
+    pub(crate) fn queued(&self) -> usize {
+        self.backlog.len() + self.parked.len() + self.limiter.as_ref().map_or(0, Limiter::len)
+    }
+
+    /// Whether every request given to the manager was answered
+    pub(crate) fn is_idle(&self) -> bool {
+        self.broadcasts.is_empty()
+            && self.backlog.is_empty()
+            && self.parked.is_empty()
+            && self.limiter.as_ref().is_none_or(Limiter::is_empty)
+            && self
+                .balancer
+                .total
+                .load(std::sync::atomic::Ordering::Relaxed)
+                == 0
+    }
+
+    /// Account for a finished request and release every response that's ready, to it's
+    /// client or to `f` for [`DEFAULT_CLIENT`]
+    pub(crate) fn complete<F>(
+        &mut self,
+        pooled_response: Pooled<Executed<Option<Ret<Req>>>>,
+        mut f: F,
+    ) where
+        F: FnMut(Seq, Reply<Ret<Req>>),
+    {
+        let (runner_id, seq, Executed { took, outcome }, _) = pooled_response.unpack();
+        let now = Instant::now();
+        let (client, enqueued, _, cost) = self
+            .owners
+            .remove(&seq)
+            .unwrap_or((DEFAULT_CLIENT, now, runner_id, 1));
+        let broadcast = self.broadcast_of.remove(&seq);
+        let orphaned = self.orphaned.remove(&seq);
+        if !orphaned {
+            // Cancelled requests didn't execute
+            let took = matches!(outcome, Ok(Some(_)) | Err(_)).then_some(took);
+            self.balancer.done(runner_id, cost, took);
+        }
+        // Broadcasts skip the backlog
+        if broadcast.is_none() {
+            self.backlog.done(client);
+        }
+        let waited = now.saturating_duration_since(enqueued).saturating_sub(took);
+        let response = match outcome {
+            // Cancelled before it started
+            Ok(None) => None,
+            Ok(Some(response)) => {
+                self.stats.completed(waited, took);
+                if let Some(hooks) = &self.hooks {
+                    hooks.on_complete(seq, runner_id, waited, took);
+                }
+                Some(Reply::Response(response))
+            }
+            Err(panic) => {
+                self.stats.failed(waited, took);
+                if let Some(hooks) = &self.hooks {
+                    hooks.on_panic(seq, runner_id, &*panic);
+                }
+                Some(Reply::Panicked(panic))
+            }
+        };
+        if let Some((first, runner)) = broadcast {
+            let gather = self.gathers.get_mut(&first).unwrap();
+            // Broadcasts answer `None` for the runners that panicked
+            gather.responses[runner] = match response {
+                Some(Reply::Response(response)) => Some(response),
+                _ => None,
+            };
+            gather.left -= 1;
+            if gather.left == 0 {
+                let gather = self.gathers.remove(&first).unwrap();
+                // The caller may be gone
+                let _ = gather.send.send(Ok(gather.responses));
+            }
+            return;
+        }
+        let clients = &self.clients;
+        let mut release = |seq, response| deliver(clients, &mut f, seq, response);
+        let response = response.map(|response| (client, response));
+        match (&mut self.reorder, response) {
+            // Orphaned requests were skipped when their runner was replaced
+            (Some(reorder), response) if !orphaned => reorder.complete(seq, response, release),
+            (_, Some(response)) => release(seq, response),
+            (_, None) => {}
+        }
+    }
+
+    /// Start a runner reporting to `beat` as it's runner of `generation`
+    fn spawn(&self, beat: Arc<Beat>, generation: usize) -> PoolCon<Req> {
+        // SAFETY: the pool's runners were spawned with the same spawner, for the same types
+        unsafe {
+            PoolConDef::new().run(
+                self.send_pooled_response.clone(),
+                self.batching,
+                &self.spawner,
+                beat,
+                generation,
+            )
+        }
+    }
+
+    /// Start new runners in place of the ones the watchdog found stuck
+    fn replace_stuck(&mut self) {
+        for id in 0..self.runners.len() {
+            let beat = self.balancer.beat(id);
+            if !beat.take_replace() {
+                continue;
+            }
+            let generation = beat.replaced();
+            self.balancer.reset(id);
+            let orphaned: Vec<Seq> = self
+                .owners
+                .iter()
+                .filter(|(_, (_, _, runner, _))| *runner == id)
+                .map(|(seq, _)| *seq)
+                .collect();
+            // Responses done after the orphaned ones aren't held back for them
+            if let Some(reorder) = &mut self.reorder {
+                let user_send_response = &self.user_send_response;
+                let mut f = |seq, response| {
+                    // The receiver may be gone
+                    let _ = user_send_response.send((seq, response));
+                };
+                for &seq in &orphaned {
+                    reorder.complete(seq, None, |seq, response| {
+                        deliver(&self.clients, &mut f, seq, response);
+                    });
+                }
+            }
+            self.orphaned.extend(orphaned);
+            let runner = self.spawn(beat, generation);
+            self.replaced
+                .push(std::mem::replace(&mut self.runners[id], runner));
+        }
+    }
+
+    /// Take new requests with `runners` runners, starting the missing ones and retiring the rest
+    ///
+    /// Runners that are the only ones handling a kind are kept, with the ones before them
+    fn resize(&mut self, runners: usize) {
+        let runners = self.balancer.keeping_kinds(runners.max(1));
+        // Retiring runners that are still there are taken back first
+        while self.runners.len() < runners {
+            let beat = self.balancer.push(RunnerSpec::default());
+            let runner = self.spawn(beat, 0);
+            self.runners.push(runner);
+        }
+        self.balancer.set_active(runners);
+        self.retire();
+    }
+
+    /// Stop the retiring runners that finished their requests, from the last one
+    fn retire(&mut self) {
+        while self.runners.len() > self.balancer.active()
+            && self.balancer.is_drained(self.runners.len() - 1)
+        {
+            let PoolCon {
+                _thread: thread,
+                send_pooled_req,
+            } = self.runners.pop().unwrap();
+            self.balancer.pop();
+            // Without a sender, the idle runner stops right away
+            drop(send_pooled_req);
+            thread.join().unwrap();
+        }
+    }
+
+    /// Resize the pool as it's [`Autoscale`] policy asks
+    fn autoscale(&mut self) {
+        // Requests held back by the rate limits wait for a runner too
+        let queued = self.queued();
+        let Some(autoscaler) = &mut self.autoscaler else {
+            return;
+        };
+        let active = self.balancer.active();
+        if let Some(runners) = autoscaler.target(active, self.balancer.busy(), queued) {
+            self.resize(runners);
+        }
+    }
+
+    fn command(&mut self, command: Command<Req>) {
+        match command {
+            Command::Submit(client, seq, env) => {
+                if let Some(hooks) = &self.hooks {
+                    hooks.on_enqueue(seq, client);
+                }
+                env.span().enqueued(self.backlog.len());
+                self.backlog.push(client, env.priority(), (seq, env));
+            }
+            Command::Join(client, share, send) => {
+                self.backlog.join(client, share);
+                self.clients.insert(client, send);
+            }
+            Command::Leave(client) => {
+                self.backlog.leave(client);
+                self.clients.remove(&client);
+            }
+            // Sent out by the next dispatch, unless the pool is paused
+            Command::Broadcast(make_req, send) => self.broadcasts.push_back((make_req, send)),
+            Command::Resize(runners) => {
+                if let Some(autoscaler) = &mut self.autoscaler {
+                    autoscaler.resized();
+                }
+                self.resize(runners);
+            }
+        }
+    }
+
+    pub(crate) fn run(
+        mut self,
+        recv_user_req: Receiver<ControlFlow<(), Command<Req>>>,
+    ) -> PoolCloserDef<Req> {
+        let user_send_response = self.user_send_response.clone();
+        loop {
+            loop {
+                match recv_user_req.try_recv() {
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        panic!("Channel closed")
+                    }
+                    Ok(ControlFlow::Continue(command)) => self.command(command),
+                    Ok(ControlFlow::Break(())) => {
+                        crate::trace::stopping(self.queued());
+                        return PoolCloserDef { manager: self };
+                    }
+                };
+            }
+            self.replace_stuck();
+            self.autoscale();
+            self.retire();
+            self.dispatch();
+            match self.recv_pooled_response.try_recv() {
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    panic!("Channel closed")
+                }
+                Ok(pooled_response) => {
+                    self.complete(pooled_response, |seq, response| {
+                        user_send_response.send((seq, response)).unwrap();
+                    });
+                }
+            }
+            std::thread::yield_now();
+        }
+    }
+}
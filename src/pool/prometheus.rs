@@ -0,0 +1,256 @@
+use super::*;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+type Source = Box<dyn Fn() -> PoolStats + Send + Sync>;
+
+/// Name, help and value of a metric family
+type Metric<T> = (&'static str, &'static str, fn(&PoolStats) -> T);
+
+/// Renders the [`PoolStats`] of named pools in the Prometheus text exposition format
+///
+/// Every sample is labeled with it's `pool`, and the in flight requests with their `runner`
+#[derive(Default)]
+pub struct Exporter {
+    pools: Vec<(String, Source)>,
+}
+
+impl std::fmt::Debug for Exporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.pools.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl Exporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Export the stats of the pool `stats` reads, labeled `pool="name"`
+    pub fn pool(mut self, name: impl Into<String>, stats: StatsHandle) -> Self {
+        self.pools
+            .push((name.into(), Box::new(move || stats.stats())));
+        self
+    }
+    /// Current stats of every pool
+    pub fn render(&self) -> String {
+        let pools: Vec<_> = self
+            .pools
+            .iter()
+            .map(|(name, stats)| (escape(name), stats()))
+            .collect();
+        let mut out = String::new();
+        let gauges: [Metric<usize>; 4] = [
+            ("paused", "Whether the pool is paused", |s| usize::from(s.paused)),
+            ("runners", "Runners taking new requests", |s| s.runners),
+            (
+                "queued",
+                "Requests waiting in the dispatcher for a runner",
+                |s| s.queued,
+            ),
+            ("delayed", "Requests held back by the rate limits", |s| {
+                s.delayed
+            }),
+        ];
+        for (name, help, value) in gauges {
+            family(&mut out, name, help, "gauge");
+            for (pool, stats) in &pools {
+                sample(&mut out, name, &format!("pool=\"{pool}\""), value(stats));
+            }
+        }
+        family(
+            &mut out,
+            "in_flight",
+            "Requests dispatched to the runner that didn't finish yet",
+            "gauge",
+        );
+        for (pool, stats) in &pools {
+            for (runner, in_flight) in stats.in_flight.iter().enumerate() {
+                let labels = format!("pool=\"{pool}\",runner=\"{runner}\"");
+                sample(&mut out, "in_flight", &labels, in_flight);
+            }
+        }
+        family(
+            &mut out,
+            "load",
+            "Summed cost of the requests in flight on the runner",
+            "gauge",
+        );
+        for (pool, stats) in &pools {
+            for (runner, load) in stats.load.iter().enumerate() {
+                let labels = format!("pool=\"{pool}\",runner=\"{runner}\"");
+                sample(&mut out, "load", &labels, load);
+            }
+        }
+        family(
+            &mut out,
+            "runner_healthy",
+            "Whether the runner is in service, 0 while the watchdog finds it stuck",
+            "gauge",
+        );
+        for (pool, stats) in &pools {
+            for (runner, healthy) in stats.healthy.iter().enumerate() {
+                let labels = format!("pool=\"{pool}\",runner=\"{runner}\"");
+                sample(&mut out, "runner_healthy", &labels, u8::from(*healthy));
+            }
+        }
+        let counters: [Metric<u64>; 3] = [
+            (
+                "completed_total",
+                "Requests executed without panicking",
+                |s| s.completed,
+            ),
+            ("failed_total", "Requests that panicked", |s| s.failed),
+            (
+                "stuck_total",
+                "Requests the watchdog flagged for running too long",
+                |s| s.stuck,
+            ),
+        ];
+        for (name, help, value) in counters {
+            family(&mut out, name, help, "counter");
+            for (pool, stats) in &pools {
+                sample(&mut out, name, &format!("pool=\"{pool}\""), value(stats));
+            }
+        }
+        let histograms: [Metric<Histogram>; 2] = [
+            (
+                "queue_wait_seconds",
+                "Time requests waited from being sent until a runner started them",
+                |s| s.queue_wait.clone(),
+            ),
+            (
+                "exec_time_seconds",
+                "Time runners took to execute requests",
+                |s| s.exec_time.clone(),
+            ),
+        ];
+        for (name, help, value) in histograms {
+            family(&mut out, name, help, "histogram");
+            for (pool, stats) in &pools {
+                histogram(&mut out, name, pool, &value(stats));
+            }
+        }
+        out
+    }
+    /// Serve [`Exporter::render`] over HTTP at `GET /metrics`, from a thread listening on `addr`
+    pub fn serve(self, addr: impl ToSocketAddrs) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        // a client going away only loses it's own response
+                        let _ = self.respond(stream);
+                    }
+                }
+            }
+        });
+        Ok(MetricsServer {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut head = Vec::new();
+        let mut buf = [0; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8192 {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..n]);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let mut line = head.lines().next().unwrap_or_default().split(' ');
+        let (status, body) = match (line.next(), line.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+            (Some("GET"), _) => ("404 Not Found", String::new()),
+            _ => ("405 Method Not Allowed", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+/// Thread serving an [`Exporter`], stopped when dropped
+#[derive(Debug)]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Address the server listens on, with the actual port when bound to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+    /// Stop listening and join the server's thread
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+    fn shutdown(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::Release);
+        // wake the thread blocked accepting connections
+        if TcpStream::connect(self.addr).is_ok() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn family(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP a_run_{name} {help}");
+    let _ = writeln!(out, "# TYPE a_run_{name} {kind}");
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "a_run_{name}{{{labels}}} {value}");
+}
+
+fn histogram(out: &mut String, name: &str, pool: &str, h: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, count) in BUCKETS.iter().zip(h.counts) {
+        cumulative += count;
+        let labels = format!("pool=\"{pool}\",le=\"{}\"", bound.as_secs_f64());
+        sample(out, &format!("{name}_bucket"), &labels, cumulative);
+    }
+    let labels = format!("pool=\"{pool}\",le=\"+Inf\"");
+    sample(out, &format!("{name}_bucket"), &labels, h.count);
+    let labels = format!("pool=\"{pool}\"");
+    sample(out, &format!("{name}_sum"), &labels, h.sum.as_secs_f64());
+    sample(out, &format!("{name}_count"), &labels, h.count);
+}
+
+/// Escape a label value
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
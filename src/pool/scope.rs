@@ -0,0 +1,131 @@
+use super::*;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Threads of a scoped pool that didn't finish yet
+#[derive(Debug, Default)]
+pub(crate) struct Running {
+    count: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl Running {
+    /// Block until every thread started in the scope finished
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.finished.wait(count).unwrap();
+        }
+    }
+}
+
+/// Held by a scoped thread until it finishes, even by panicking
+struct Finish(Arc<Running>);
+
+impl Finish {
+    fn new(running: &Arc<Running>) -> Self {
+        *running.count.lock().unwrap() += 1;
+        Self(running.clone())
+    }
+}
+
+impl Drop for Finish {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap() -= 1;
+        self.0.finished.notify_all();
+    }
+}
+
+/// Waits for the scope's threads when dropped, so borrows outlive them even while unwinding
+struct WaitRunning(Arc<Running>);
+
+impl Drop for WaitRunning {
+    fn drop(&mut self) {
+        self.0.wait();
+    }
+}
+
+/// How the pool's threads are started
+#[derive(Debug, Clone)]
+pub(crate) enum Spawner {
+    Detached,
+    /// Tracked, so [`Pool::scope`] can wait for them
+    Scoped(Arc<Running>),
+}
+
+impl Spawner {
+    /// # Safety
+    ///
+    /// A [`Spawner::Detached`] thread may outlive everything, so `f` and `T` must be `'static`.
+    /// Whatever a [`Spawner::Scoped`] thread borrows must outlive [`Running::wait`]
+    pub(crate) unsafe fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send,
+        T: Send,
+    {
+        let builder = std::thread::Builder::new();
+        // SAFETY: upheld by the caller
+        unsafe {
+            match self {
+                Spawner::Detached => builder.spawn_unchecked(f),
+                Spawner::Scoped(running) => {
+                    let finish = Finish::new(running);
+                    builder.spawn_unchecked(move || {
+                        let _finish = finish;
+                        f()
+                    })
+                }
+            }
+        }
+        .expect("failed to spawn thread")
+    }
+}
+
+impl<Req> Pool<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    /// Run a pool whose requests may borrow from the caller, like [`std::thread::scope`]
+    ///
+    /// Only requests may borrow, responses are still `'static`
+    ///
+    /// `f` sends requests and receives responses through the pool's api. Once it returns the
+    /// pool is closed with `closer`, responses `f` didn't receive are dropped, and every thread
+    /// of the pool is joined before returning. A panic in `f` is propagated after that, or
+    /// else the panic of a request whose response `f` didn't receive
+    ///
+    /// # Panics
+    ///
+    /// If the pool's watchdog [replaces](Watchdog::replace) stuck runners, the threads it leaves
+    /// behind would keep the scope waiting for as long as they're stuck
+    pub fn scope<S, F, T>(self, closer: &S, f: F) -> T
+    where
+        S: StopRunner<Req>,
+        F: FnOnce(&PoolApi<Req>) -> T,
+    {
+        assert!(
+            !self.watchdog.as_ref().is_some_and(Watchdog::replaces),
+            "a scoped pool can't replace stuck runners"
+        );
+        let running = Arc::new(Running::default());
+        let _wait = WaitRunning(running.clone());
+        // SAFETY: the borrows in `Req` outlive this call, and `_wait` outlives the pool's threads
+        let api = unsafe { self.launch(Spawner::Scoped(running)) };
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| f(&api)));
+        let closed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            match api.stop_and_close() {
+                Ok(pool_closer) => pool_closer.close_capture(closer),
+                Err(_) => Vec::new(),
+            }
+        }));
+        let panic = match (res, closed) {
+            (Ok(v), Ok(late)) => match late.into_iter().find_map(Result::err) {
+                Some(panic) => panic,
+                None => return v,
+            },
+            (Err(panic), _) | (Ok(_), Err(panic)) => panic,
+        };
+        std::panic::resume_unwind(panic)
+    }
+}
@@ -0,0 +1,139 @@
+use super::*;
+use crate::service::{Capacity, Permit, ResponseFuture, ServiceError};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+/// Response channel of a request a [`PoolService`] is waiting for
+type Answer<Req> = oneshot::Sender<Result<Ret<Req>, ServiceError>>;
+
+/// Response channels of the requests a [`PoolService`] is waiting for, `None` once the pool
+/// stopped answering
+type Pending<Req> = Arc<Mutex<Option<HashMap<Seq, Answer<Req>>>>>;
+
+/// [`tower::Service`] submitting requests to a pool as one of it's clients, see [`PoolApi::service`]
+pub struct PoolService<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    id: ClientId,
+    send_req: Sender<ControlFlow<(), Command<Req>>>,
+    next_seq: Arc<AtomicUsize>,
+    layers: Option<Arc<Layers<Req>>>,
+    recorder: Option<Recorder>,
+    balancer: Arc<PoolBalancer>,
+    pending: Pending<Req>,
+    capacity: Arc<Capacity>,
+    permit: Option<Permit>,
+}
+
+impl<Req> PoolApi<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    /// Make a [`tower::Service`] submitting requests as a new client with `share` of the pool,
+    /// at most `capacity` of them waiting for their response at once
+    ///
+    /// A thread hands the client's responses to the futures waiting for them, a request that
+    /// panics resolves with [`ServiceError::Panicked`]. Once the pool stops the futures still
+    /// waiting resolve with [`ServiceError::Dropped`]
+    pub fn service(&self, share: Share, capacity: usize) -> PoolService<Req> {
+        let (id, recv_res) = self.join(share);
+        let pending: Pending<Req> = Arc::new(Mutex::new(Some(HashMap::new())));
+        let waiting = pending.clone();
+        std::thread::spawn(move || {
+            while let Ok((seq, reply)) = recv_res.recv() {
+                let send = waiting
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .and_then(|answers| answers.remove(&seq));
+                if let Some(send) = send {
+                    let res = match reply {
+                        Reply::Response(res) => Ok(res),
+                        Reply::Panicked(_) => Err(ServiceError::Panicked),
+                    };
+                    // The caller may have dropped the future
+                    let _ = send.send(res);
+                }
+            }
+            // Dropping the channels resolves their futures
+            waiting.lock().unwrap().take();
+        });
+        PoolService {
+            id,
+            send_req: self.send_req.clone(),
+            next_seq: self.next_seq.clone(),
+            layers: self.layers.clone(),
+            recorder: self.recorder.clone(),
+            balancer: self.balancer.clone(),
+            pending,
+            capacity: Capacity::new(capacity),
+            permit: None,
+        }
+    }
+}
+
+impl<Req> tower::Service<Req> for PoolService<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    type Response = Ret<Req>;
+    type Error = ServiceError;
+    type Future = ResponseFuture<Ret<Req>>;
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.permit.is_none() {
+            self.permit = Some(std::task::ready!(self.capacity.poll_acquire(cx)));
+        }
+        Poll::Ready(Ok(()))
+    }
+    fn call(&mut self, req: Req) -> Self::Future {
+        let permit = self
+            .permit
+            .take()
+            .expect("poll_ready must be called before call");
+        let (send, recv) = oneshot::channel();
+        // Held while submitting, so the response can't arrive before it's channel
+        let mut waiting = self.pending.lock().unwrap();
+        let env = Envelope::new(req)
+            .layered(self.layers.as_ref())
+            .recorded(self.recorder.as_ref());
+        let Some(answers) = waiting.as_mut() else {
+            return ResponseFuture::outcome(None, None, permit);
+        };
+        if !self.balancer.handles(env.kind()) {
+            // The receiver is right there
+            let _ = send.send(Err(ServiceError::Unhandled));
+            return ResponseFuture::outcome(Some(recv), None, permit);
+        }
+        let submitted = submit(&self.send_req, &self.next_seq, &self.balancer, self.id, env);
+        let Ok(ticket) = submitted else {
+            return ResponseFuture::outcome(None, None, permit);
+        };
+        answers.insert(ticket.seq(), send);
+        let pending = self.pending.clone();
+        let abandon = move || {
+            let unanswered = pending
+                .lock()
+                .unwrap()
+                .as_mut()
+                .and_then(|answers| answers.remove(&ticket.seq()));
+            if unanswered.is_some() {
+                ticket.cancel();
+            }
+        };
+        ResponseFuture::outcome(Some(recv), Some(Box::new(abandon)), permit)
+    }
+}
+
+impl<Req> Drop for PoolService<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    fn drop(&mut self) {
+        let _ = self
+            .send_req
+            .send(ControlFlow::Continue(Command::Leave(self.id)));
+    }
+}
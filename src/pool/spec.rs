@@ -0,0 +1,78 @@
+/// What a pool's runner can take, see [`Pool::runner`](super::Pool::runner)
+///
+/// The balancer sends each request to the runner with the fewest requests per unit of weight
+/// among the ones that handle it's [`kind`](crate::runner::ControlExecuteMessage::kind) and have room left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunnerSpec {
+    pub(crate) weight: usize,
+    capacity: Option<usize>,
+    pub(crate) kinds: Option<Vec<u64>>,
+}
+
+impl Default for RunnerSpec {
+    fn default() -> Self {
+        Self {
+            weight: 1,
+            capacity: None,
+            kinds: None,
+        }
+    }
+}
+
+impl RunnerSpec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// How much work the runner takes relative to the others, it gets `weight` times the
+    /// requests and `weight` times [`Pool::depth`](super::Pool::depth) of them queued, 1 by default
+    pub fn weight(mut self, weight: usize) -> Self {
+        self.weight = weight.max(1);
+        self
+    }
+    /// Most requests the runner may have at once, instead of it's weighted depth
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity.max(1));
+        self
+    }
+    /// Only take requests of these kinds and the ones without a kind, every kind by default
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = u64>) -> Self {
+        self.kinds = Some(kinds.into_iter().collect());
+        self
+    }
+    pub(crate) fn handles(&self, kind: Option<u64>) -> bool {
+        match (&self.kinds, kind) {
+            (Some(kinds), Some(kind)) => kinds.contains(&kind),
+            _ => true,
+        }
+    }
+    /// Requests the runner may have when the pool's depth is `depth`
+    pub(crate) fn limit(&self, depth: usize) -> usize {
+        self.capacity.unwrap_or(depth * self.weight)
+    }
+}
+
+/// How a pool's balancer picks among the runners that may take a request, see
+/// [`Pool::strategy`](super::Pool::strategy)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strategy {
+    /// The runner with the least summed
+    /// [`cost`](crate::runner::ControlExecuteMessage::cost) of requests per unit of
+    /// [`weight`](RunnerSpec::weight)
+    #[default]
+    LeastLoaded,
+    /// The runner expected to finish it's requests and the new one first, from an exponentially
+    /// weighted moving average of how long it took per unit of cost so far
+    ///
+    /// Each finished request moves the average `alpha` of the way towards it's own time, `alpha`
+    /// is in `(0, 1]`.
+    /// Runners that never finished a request are expected to take as long as the others on
+    /// average, and weights are ignored
+    Latency { alpha: f64 },
+}
+
+impl Strategy {
+    /// [`Strategy::Latency`] with an `alpha` of 0.2
+    pub fn latency() -> Self {
+        Self::Latency { alpha: 0.2 }
+    }
+}
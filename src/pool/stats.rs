@@ -0,0 +1,201 @@
+use super::*;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the buckets of every [`Histogram`], the last bucket holds the rest
+pub const BUCKETS: [Duration; 8] = [
+    Duration::from_micros(1),
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+/// Durations counted per bucket of [`BUCKETS`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// Durations up to each bound of [`BUCKETS`] and over the last one, not cumulative
+    pub counts: [u64; BUCKETS.len() + 1],
+    pub count: u64,
+    pub sum: Duration,
+}
+
+#[derive(Debug, Default)]
+struct AtomicHistogram {
+    counts: [AtomicU64; BUCKETS.len() + 1],
+    sum_nanos: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn record(&self, d: Duration) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| d <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(d.as_nanos()).unwrap_or(u64::MAX);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+    fn snapshot(&self) -> Histogram {
+        let counts = std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed));
+        Histogram {
+            counts,
+            count: counts.iter().sum(),
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Snapshot of a pool's activity, see [`PoolApi::stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Requests waiting in the dispatcher for a runner
+    pub queued: usize,
+    /// Requests held back by the rate limits
+    pub delayed: usize,
+    /// Whether the pool is paused, see [`PoolApi::pause`]
+    pub paused: bool,
+    /// Runners taking new requests, see [`PoolApi::resize`]
+    pub runners: usize,
+    /// Requests dispatched to each runner that didn't finish yet, including the retiring ones
+    pub in_flight: Vec<usize>,
+    /// Summed [`ControlExecuteMessage::cost`] of the requests in flight on each runner
+    pub load: Vec<u64>,
+    /// Average execution time per unit of cost of each runner, with [`Strategy::Latency`] once
+    /// it finished a request
+    pub latency: Vec<Option<Duration>>,
+    /// Whether each runner is in service, see [`Watchdog`]
+    pub healthy: Vec<bool>,
+    /// Requests executed without panicking, including the ones answered with [`DeadlineExceeded`]
+    pub completed: u64,
+    /// Requests that panicked
+    pub failed: u64,
+    /// Requests the [`Watchdog`] flagged for running too long
+    pub stuck: u64,
+    /// Time requests waited from being sent until a runner started them
+    pub queue_wait: Histogram,
+    /// Time runners took to execute requests
+    pub exec_time: Histogram,
+}
+
+/// Counters shared between the pool's manager, which updates them, and it's [`PoolApi`]
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    queued: AtomicUsize,
+    delayed: AtomicUsize,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    stuck: AtomicU64,
+    paused: AtomicBool,
+    queue_wait: AtomicHistogram,
+    exec_time: AtomicHistogram,
+}
+
+impl Stats {
+    pub(crate) fn set_queued(&self, queued: usize, delayed: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+        self.delayed.store(delayed, Ordering::Relaxed);
+    }
+    pub(crate) fn completed(&self, waited: Duration, took: Duration) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        self.queue_wait.record(waited);
+        self.exec_time.record(took);
+    }
+    pub(crate) fn failed(&self, waited: Duration, took: Duration) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.queue_wait.record(waited);
+        self.exec_time.record(took);
+    }
+    pub(crate) fn stuck(&self) {
+        self.stuck.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+    pub(crate) fn snapshot(
+        &self,
+        runners: usize,
+        in_flight: Vec<usize>,
+        load: Vec<u64>,
+        latency: Vec<Option<Duration>>,
+        healthy: Vec<bool>,
+    ) -> PoolStats {
+        PoolStats {
+            queued: self.queued.load(Ordering::Relaxed),
+            delayed: self.delayed.load(Ordering::Relaxed),
+            paused: self.is_paused(),
+            runners,
+            in_flight,
+            load,
+            latency,
+            healthy,
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            stuck: self.stuck.load(Ordering::Relaxed),
+            queue_wait: self.queue_wait.snapshot(),
+            exec_time: self.exec_time.snapshot(),
+        }
+    }
+}
+
+/// Reads a pool's [`PoolStats`] from any thread, see [`PoolApi::stats_handle`]
+#[derive(Debug)]
+pub struct StatsHandle {
+    stats: Arc<Stats>,
+    balancer: Arc<PoolBalancer>,
+}
+
+impl Clone for StatsHandle {
+    fn clone(&self) -> Self {
+        Self {
+            stats: self.stats.clone(),
+            balancer: self.balancer.clone(),
+        }
+    }
+}
+
+impl StatsHandle {
+    pub(crate) fn new(stats: &Arc<Stats>, balancer: &Arc<PoolBalancer>) -> Self {
+        Self {
+            stats: stats.clone(),
+            balancer: balancer.clone(),
+        }
+    }
+    pub fn stats(&self) -> PoolStats {
+        self.stats.snapshot(
+            self.balancer.active(),
+            self.balancer.running(),
+            self.balancer.load(),
+            self.balancer.latency(),
+            self.balancer.healthy(),
+        )
+    }
+}
+
+/// Called by the pool's manager thread as requests go through the pool, see [`Pool::hooks`]
+#[allow(unused_variables)]
+pub trait PoolHooks: Send + Sync {
+    /// The manager took the request from it's client
+    fn on_enqueue(&self, seq: Seq, client: ClientId) {}
+    /// The request was handed to `runner`
+    fn on_dispatch(&self, seq: Seq, runner: usize) {}
+    /// `runner` executed the request, after it waited `waited` since it was sent
+    fn on_complete(&self, seq: Seq, runner: usize, waited: Duration, took: Duration) {}
+    /// The request panicked on `runner`, the runner goes on and whoever receives the request's
+    /// response gets the panic instead
+    fn on_panic(&self, seq: Seq, runner: usize, panic: &(dyn Any + Send)) {}
+}
+
+/// What a pool's runner did with a request
+#[derive(Debug)]
+pub struct Executed<T> {
+    pub(crate) took: Duration,
+    pub(crate) outcome: Result<T, Box<dyn Any + Send>>,
+}
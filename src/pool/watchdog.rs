@@ -0,0 +1,182 @@
+use super::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+thread_local! {
+    /// Beat of the pool runner executing on this thread, and the generation it was spawned as
+    static BEAT: RefCell<Option<(Arc<Beat>, usize)>> = const { RefCell::new(None) };
+}
+
+/// What a runner slot of a pool is executing, shared between it's runner and the watchdog
+#[derive(Debug)]
+pub(crate) struct Beat {
+    epoch: Instant,
+    /// Nanoseconds since `epoch` plus one when the current request started, 0 while idle
+    started: AtomicU64,
+    /// Bumped when the slot's runner is replaced, so the stuck one stops reporting
+    generation: AtomicUsize,
+    healthy: AtomicBool,
+    /// The watchdog asks the manager to replace the runner
+    replace: AtomicBool,
+}
+
+impl Default for Beat {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            started: AtomicU64::new(0),
+            generation: AtomicUsize::new(0),
+            healthy: AtomicBool::new(true),
+            replace: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Beat {
+    pub(crate) fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Acquire)
+    }
+    pub(crate) fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+    /// How long the current request has been executing
+    fn busy_for(&self) -> Option<Duration> {
+        match self.started.load(Ordering::Acquire) {
+            0 => None,
+            started => Some(
+                self.epoch
+                    .elapsed()
+                    .saturating_sub(Duration::from_nanos(started - 1)),
+            ),
+        }
+    }
+    /// Whether the watchdog asked for the runner to be replaced, once
+    pub(crate) fn take_replace(&self) -> bool {
+        self.replace.swap(false, Ordering::AcqRel)
+    }
+    /// Forget the stuck runner, returning the generation of the one replacing it
+    pub(crate) fn replaced(&self) -> usize {
+        self.started.store(0, Ordering::Release);
+        self.healthy.store(true, Ordering::Release);
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+    /// Report the requests of the current thread to `beat`, as it's runner of `generation`
+    pub(crate) fn install(beat: Arc<Beat>, generation: usize) {
+        BEAT.with(|current| current.replace(Some((beat, generation))));
+    }
+}
+
+/// Marks the current thread's runner busy until dropped
+pub(crate) struct Busy(Option<(Arc<Beat>, usize)>);
+
+impl Busy {
+    pub(crate) fn start() -> Self {
+        let beat = BEAT.with(|current| current.borrow().clone());
+        let beat = beat.filter(|(beat, generation)| beat.generation() == *generation);
+        if let Some((beat, _)) = &beat {
+            let started = u64::try_from(beat.epoch.elapsed().as_nanos()).unwrap_or(u64::MAX - 1);
+            beat.started.store(started + 1, Ordering::Release);
+        }
+        Self(beat)
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        // The runner may have been replaced meanwhile
+        if let Some((beat, generation)) = &self.0
+            && beat.generation() == *generation
+        {
+            beat.started.store(0, Ordering::Release);
+            // A runner that finally returned is back in service
+            beat.healthy.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Flags requests executing for longer than a threshold, taking their runner out of service
+/// until they return, see [`Pool::watchdog`]
+pub struct Watchdog {
+    threshold: Duration,
+    interval: Option<Duration>,
+    on_stuck: Option<Box<dyn Fn(usize, Duration) + Send + Sync>>,
+    replace: bool,
+}
+
+impl std::fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("threshold", &self.threshold)
+            .field("interval", &self.interval)
+            .field("replace", &self.replace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Watchdog {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            interval: None,
+            on_stuck: None,
+            replace: false,
+        }
+    }
+    /// How often runners are checked, a quarter of the threshold by default
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+    /// Call `on_stuck` with the runner and how long it's been executing when it's flagged
+    pub fn on_stuck<F>(mut self, on_stuck: F) -> Self
+    where
+        F: Fn(usize, Duration) + Send + Sync + 'static,
+    {
+        self.on_stuck = Some(Box::new(on_stuck));
+        self
+    }
+    /// Start a new runner in place of a stuck one, instead of waiting for it to return
+    ///
+    /// The stuck runner's thread is left behind with the requests queued to it, their responses
+    /// are still delivered if it ever returns. An ordered pool doesn't hold later responses
+    /// back for them, theirs come out of order. [`Pool::scope`] refuses it, it has to wait for
+    /// every runner's thread
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+    pub(crate) fn replaces(&self) -> bool {
+        self.replace
+    }
+    /// Check the runners until `stop` is dropped
+    pub(crate) fn watch(
+        self,
+        balancer: &PoolBalancer,
+        stats: &Stats,
+        stop: &Receiver<()>,
+    ) {
+        let interval = self
+            .interval
+            .unwrap_or(self.threshold / 4)
+            .max(Duration::from_millis(1));
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            for (runner, beat) in balancer.beats().into_iter().enumerate() {
+                let Some(busy) = beat.busy_for() else {
+                    continue;
+                };
+                if busy < self.threshold || !beat.is_healthy() {
+                    continue;
+                }
+                beat.healthy.store(false, Ordering::Release);
+                stats.stuck();
+                if let Some(on_stuck) = &self.on_stuck {
+                    on_stuck(runner, busy);
+                }
+                if self.replace {
+                    beat.replace.store(true, Ordering::Release);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// Scheduling priority of a request, higher priorities are taken first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Priority(pub u8);
+
+impl Priority {
+    pub const MIN: Self = Self(0);
+    pub const LOW: Self = Self(64);
+    pub const NORMAL: Self = Self(128);
+    pub const HIGH: Self = Self(192);
+    pub const MAX: Self = Self(u8::MAX);
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+/// Requests taken ahead of a waiting request before it's priority is raised by one level
+pub const AGING: usize = 16;
+
+/// FIFO lanes of requests, one per priority, taking the highest priority first
+///
+/// A waiting request gains one level of priority for every [`AGING`] requests taken before it,
+/// so low priority requests can't starve
+#[derive(Debug)]
+pub struct Lanes<T> {
+    lanes: BTreeMap<Priority, VecDeque<(usize, T)>>,
+    /// Requests taken so far, used as the clock for aging
+    taken: usize,
+    len: usize,
+}
+
+impl<T> Default for Lanes<T> {
+    fn default() -> Self {
+        Self {
+            lanes: BTreeMap::new(),
+            taken: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<T> Lanes<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn push(&mut self, priority: Priority, v: T) {
+        self.lanes
+            .entry(priority)
+            .or_default()
+            .push_back((self.taken, v));
+        self.len += 1;
+    }
+    /// Take the request with the highest aged priority, the oldest one on ties
+    pub fn pop(&mut self) -> Option<T> {
+        let (priority, _, _) = self
+            .lanes
+            .iter()
+            .filter_map(|(priority, lane)| {
+                let (since, _) = lane.front()?;
+                let aged = usize::from(priority.0) + (self.taken - since) / AGING;
+                Some((*priority, aged, *since))
+            })
+            .max_by(|(_, a_aged, a_since), (_, b_aged, b_since)| {
+                a_aged.cmp(b_aged).then(b_since.cmp(a_since))
+            })?;
+        let lane = self.lanes.get_mut(&priority)?;
+        let (_, v) = lane.pop_front()?;
+        if lane.is_empty() {
+            self.lanes.remove(&priority);
+        }
+        self.taken += 1;
+        self.len -= 1;
+        Some(v)
+    }
+}
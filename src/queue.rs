@@ -1,7 +1,13 @@
-use crate::runner::{ControlExecuteMessage, Ret};
+use crate::cancel::CancelHandle;
+use crate::layer::Layers;
+use crate::priority::{Lanes, Priority};
+use crate::runner::{Batching, ControlExecuteMessage, DeadlineExceeded, Envelope, Ret};
+use crate::timeline::Recorder;
 use std::ops::ControlFlow;
-use std::sync::mpsc::{self, Receiver, RecvError, SendError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, SendError, Sender};
 use std::thread::JoinHandle;
+use std::time::Instant;
 
 pub struct Runner<Req>
 where
@@ -10,6 +16,33 @@ where
 {
     incoming: Receiver<Req>,
     outgoing: Sender<Ret<Req>>,
+    /// Requests already taken from `incoming`, waiting for their turn
+    queued: Lanes<Req>,
+    batching: Option<Batching>,
+    pause: Option<Arc<Pause>>,
+}
+
+/// Holds a runner back from starting requests while set, see [`RunnerApi::pause`]
+#[derive(Debug, Default)]
+struct Pause {
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl Pause {
+    fn set(&self, paused: bool) {
+        *self.paused.lock().unwrap() = paused;
+        self.resumed.notify_all();
+    }
+    fn is_set(&self) -> bool {
+        *self.paused.lock().unwrap()
+    }
+    fn wait(&self) {
+        let mut paused = self.paused.lock().unwrap();
+        while *paused {
+            paused = self.resumed.wait(paused).unwrap();
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -20,7 +53,7 @@ pub enum RunnerError<Res> {
 
 impl<Req> Runner<Req>
 where
-    Req: ControlExecuteMessage,
+    Req: ControlExecuteMessage + 'static,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
     pub fn make_unbound() -> (Sender<Req>, Receiver<Ret<Req>>) {
@@ -33,17 +66,107 @@ where
         req_recv: Receiver<Req>,
         res_send: Sender<Ret<Req>>,
     ) -> std::thread::JoinHandle<()> {
+        Self::make_bound_batched(req_recv, res_send, None)
+    }
+    /// Like [`Runner::make_bound`], executing requests in batches when `batching` is set
+    pub fn make_bound_batched(
+        req_recv: Receiver<Req>,
+        res_send: Sender<Ret<Req>>,
+        batching: Option<Batching>,
+    ) -> std::thread::JoinHandle<()> {
+        Self::bound(req_recv, res_send, batching).run_thread()
+    }
+    pub fn run_thread(self) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || self.run())
+    }
+}
+
+impl<Req> Runner<Req>
+where
+    Req: ControlExecuteMessage,
+    Ret<Req>: std::fmt::Debug + Send,
+{
+    pub(crate) fn bound(
+        req_recv: Receiver<Req>,
+        res_send: Sender<Ret<Req>>,
+        batching: Option<Batching>,
+    ) -> Self {
         Runner {
             incoming: req_recv,
             outgoing: res_send,
+            queued: Lanes::new(),
+            batching,
+            pause: None,
+        }
+    }
+    /// Execute requests on the current thread until one stops the runner, or every sender of
+    /// requests is gone once the queued ones are done
+    pub(crate) fn run(mut self) {
+        loop {
+            match self.execute_one() {
+                Ok(ControlFlow::Continue(())) => {}
+                Ok(ControlFlow::Break(())) | Err(RunnerError::Recv(_)) => return,
+                Err(e) => panic!("{e:?}"),
+            }
         }
-        .run_thread()
     }
-    pub fn run_thread(mut self) -> std::thread::JoinHandle<()> {
-        std::thread::spawn(move || while self.execute_one().unwrap().is_continue() {})
+    /// Highest priority request, waiting while the runner is paused
+    ///
+    /// A request arriving while waiting for one is queued first, so a pause set meanwhile
+    /// holds it back and a higher priority one sent during the pause still overtakes it
+    fn take(&mut self) -> Result<Req, RunnerError<Ret<Req>>> {
+        loop {
+            self.hold();
+            for msg in self.incoming.try_iter() {
+                self.queued.push(msg.priority(), msg);
+            }
+            if let Some(msg) = self.queued.pop() {
+                return Ok(msg);
+            }
+            let msg = self.incoming.recv().map_err(RunnerError::Recv)?;
+            self.queued.push(msg.priority(), msg);
+        }
+    }
+    /// Take up to `batching.max` requests, waiting up to `batching.wait` after the first one
+    fn take_batch(&mut self, batching: Batching) -> Result<Vec<Req>, RunnerError<Ret<Req>>> {
+        let mut batch = vec![self.take()?];
+        let until = Instant::now() + batching.wait;
+        while batch.len() < batching.max {
+            for msg in self.incoming.try_iter() {
+                self.queued.push(msg.priority(), msg);
+            }
+            if let Some(msg) = self.queued.pop() {
+                batch.push(msg);
+                continue;
+            }
+            match self
+                .incoming
+                .recv_timeout(until.saturating_duration_since(Instant::now()))
+            {
+                Ok(msg) => batch.push(msg),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Ok(batch)
+    }
+    /// Wait while the runner is paused
+    fn hold(&self) {
+        if let Some(pause) = &self.pause {
+            pause.wait();
+        }
     }
     fn execute_one(&mut self) -> Result<ControlFlow<()>, RunnerError<Ret<Req>>> {
-        let msg = self.incoming.recv().map_err(RunnerError::Recv)?;
+        if let Some(batching) = self.batching {
+            let batch = self.take_batch(batching)?;
+            for res in Req::execute_batch(batch) {
+                match res {
+                    ControlFlow::Continue(m) => self.outgoing.send(m).map_err(RunnerError::Send)?,
+                    ControlFlow::Break(()) => return Ok(ControlFlow::Break(())),
+                }
+            }
+            return Ok(ControlFlow::Continue(()));
+        }
+        let msg = self.take()?;
         let res = msg.execute();
         Ok(match res {
             ControlFlow::Continue(m) => {
@@ -60,45 +183,129 @@ where
     Req: ControlExecuteMessage,
     Ret<Req>: std::fmt::Debug + Send,
 {
-    send_req: Sender<Req>,
-    recv_ret: Receiver<Ret<Req>>,
+    send_req: Sender<Envelope<Req>>,
+    recv_ret: Receiver<Option<Ret<Req>>>,
     thread: JoinHandle<()>,
+    layers: Option<Arc<Layers<Req>>>,
+    recorder: Option<Recorder>,
+    pause: Arc<Pause>,
+    batched: bool,
 }
 
 impl<Req> RunnerApi<Req>
 where
-    Req: ControlExecuteMessage,
+    Req: ControlExecuteMessage + 'static,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
+    fn submit(&self, env: Envelope<Req>) -> Result<CancelHandle, SendError<Req>> {
+        let handle = env.handle();
+        self.send_req
+            .send(
+                env.layered(self.layers.as_ref())
+                    .recorded(self.recorder.as_ref()),
+            )
+            .map_err(|e| SendError(e.0.into_inner()))?;
+        Ok(handle)
+    }
+    /// Send a request that's taken before the ones with a lower priority
+    pub fn send_with_priority(
+        &self,
+        req: Req,
+        priority: Priority,
+    ) -> Result<CancelHandle, SendError<Req>> {
+        self.submit(Envelope::with_priority(req, priority))
+    }
+    /// Send a request that's skipped and answered with [`DeadlineExceeded`] if it's not executed before `deadline`
+    pub fn send_with_deadline(
+        &self,
+        req: Req,
+        deadline: Instant,
+    ) -> Result<CancelHandle, SendError<Req>>
+    where
+        Ret<Req>: From<DeadlineExceeded>,
+    {
+        self.submit(Envelope::with_deadline(req, deadline))
+    }
+    fn start(batching: Option<Batching>) -> Self {
+        let (res_send, res_recv) = mpsc::channel();
+        let (req_send, req_recv) = mpsc::channel();
+        let pause = Arc::new(Pause::default());
+        let mut runner = Runner::bound(req_recv, res_send, batching);
+        runner.pause = Some(pause.clone());
+        Self {
+            send_req: req_send,
+            recv_ret: res_recv,
+            thread: runner.run_thread(),
+            layers: None,
+            recorder: None,
+            pause,
+            batched: batching.is_some(),
+        }
+    }
+    /// Start a runner that executes requests in batches
+    pub fn with_batching(batching: Batching) -> Self {
+        Self::start(Some(batching))
+    }
+    /// Execute the requests sent from now on through `layers`
+    ///
+    /// # Panics
+    ///
+    /// If the runner executes requests in batches, layers wrap requests one at a time and
+    /// can't wrap a batch
+    pub fn layers(mut self, layers: Layers<Req>) -> Self {
+        assert!(
+            !self.batched,
+            "batching runners can't execute requests through layers"
+        );
+        self.layers = Some(Arc::new(layers));
+        self
+    }
+    /// Record the timeline of the requests sent from now on with `recorder`
+    pub fn record(mut self, recorder: &Recorder) -> Self {
+        self.recorder = Some(recorder.clone());
+        self
+    }
+    /// Stop starting requests until [`RunnerApi::resume`], requests sent meanwhile are queued
+    ///
+    /// The request executing when the runner is paused finishes normally
+    pub fn pause(&self) {
+        self.pause.set(true);
+    }
+    pub fn resume(&self) {
+        self.pause.set(false);
+    }
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_set()
+    }
+    /// Receive the next response, requests cancelled before they started have none
     pub fn recv(&self) -> Result<Ret<Req>, RecvError> {
-        self.recv_ret.recv()
+        loop {
+            if let Some(res) = self.recv_ret.recv()? {
+                return Ok(res);
+            }
+        }
     }
 }
 
 impl<Req> crate::runner::RunnerApi for RunnerApi<Req>
 where
-    Req: ControlExecuteMessage,
+    Req: ControlExecuteMessage + 'static,
     Ret<Req>: std::fmt::Debug + Send + 'static,
 {
     type Req = Req;
-    type SendAck = Result<(), SendError<Req>>;
+    type SendAck = Result<CancelHandle, SendError<Req>>;
     type CloseResult = Result<(), SendError<Req>>;
     fn new() -> Self {
-        let (res_send, res_recv) = mpsc::channel();
-        let (req_send, req_recv) = mpsc::channel();
-        let thread = Runner::make_bound(req_recv, res_send);
-        Self {
-            send_req: req_send,
-            recv_ret: res_recv,
-            thread,
-        }
+        Self::start(None)
     }
     fn send(&self, req: Self::Req) -> Self::SendAck {
-        self.send_req.send(req)
+        self.submit(Envelope::new(req))
     }
     // TODO better error
+    /// Closing a paused runner resumes it, so the requests queued before the stop execute
     fn close(self, s: impl crate::runner::StopRunner<Req>) -> Self::CloseResult {
-        self.send_req.send(s.get())?;
+        self.submit(Envelope::stop(s.get()))?;
+        self.resume();
         self.thread.join().unwrap();
         Ok(())
     }
@@ -0,0 +1,180 @@
+use std::hash::{BuildHasher, RandomState};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::priority::Priority;
+use crate::runner::{ControlExecuteMessage, Ret, StopRunner};
+
+/// Longest delay [`RetryPolicy::backoff`] accepts between two attempts
+pub const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How often a runner waiting between attempts checks whether the request was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// How a [`Retry`] request is executed again
+///
+/// The delay before attempt `n + 1` is picked at random between zero and
+/// `base * 2^(n - 1)`, capped at `max_delay`
+///
+/// The runner waits out the delay itself, so the requests queued behind a retried one wait
+/// too. On a pool that's one runner out of service for up to `max_delay` per attempt
+pub struct RetryPolicy<Res> {
+    max_attempts: u32,
+    base: Duration,
+    max_delay: Duration,
+    retryable: Box<dyn Fn(&Res) -> bool + Send + Sync>,
+}
+
+impl<Res> std::fmt::Debug for RetryPolicy<Res> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base", &self.base)
+            .field("max_delay", &self.max_delay)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Res> RetryPolicy<Res> {
+    /// Make up to `max_attempts` attempts while `retryable` holds for the result
+    pub fn new<F>(max_attempts: u32, retryable: F) -> Self
+    where
+        F: Fn(&Res) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            retryable: Box::new(retryable),
+        }
+    }
+    /// Wait from `base` up to `max_delay` between attempts, capped at [`MAX_BACKOFF`]
+    pub fn backoff(mut self, base: Duration, max_delay: Duration) -> Self {
+        self.max_delay = max_delay.min(MAX_BACKOFF);
+        self.base = base.min(self.max_delay);
+        self
+    }
+    fn delay(&self, attempt: u32) -> Duration {
+        let cap = self
+            .base
+            .saturating_mul(1 << (attempt - 1).min(31))
+            .min(self.max_delay);
+        let nanos = u64::try_from(cap.as_nanos()).unwrap_or(u64::MAX);
+        let jitter = RandomState::new().hash_one(attempt) % nanos.saturating_add(1);
+        Duration::from_nanos(jitter)
+    }
+}
+
+/// Result of a [`Retry`] request, with how many attempts it took
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retried<T> {
+    pub attempts: u32,
+    pub result: T,
+}
+
+/// Request executed again, following it's [`RetryPolicy`], while it's result is retryable
+///
+/// The runner sleeps between attempts, and stops retrying once the request is cancelled, even
+/// in the middle of a delay
+pub struct Retry<Req>
+where
+    Req: ControlExecuteMessage + Clone,
+{
+    req: Req,
+    policy: Option<Arc<RetryPolicy<Ret<Req>>>>,
+}
+
+impl<Req> std::fmt::Debug for Retry<Req>
+where
+    Req: ControlExecuteMessage + Clone + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Retry")
+            .field("req", &self.req)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<Req> Retry<Req>
+where
+    Req: ControlExecuteMessage + Clone,
+{
+    pub fn new(req: Req, policy: &Arc<RetryPolicy<Ret<Req>>>) -> Self {
+        Self {
+            req,
+            policy: Some(policy.clone()),
+        }
+    }
+    /// Wraps a request that's executed only once, like stop requests
+    pub fn once(req: Req) -> Self {
+        Self { req, policy: None }
+    }
+}
+
+impl<Req> ControlExecuteMessage for Retry<Req>
+where
+    Req: ControlExecuteMessage + Clone,
+{
+    type Res = Retried<Ret<Req>>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let mut attempts = 1;
+        loop {
+            let result = self.req.clone().execute()?;
+            let retry = self.policy.as_ref().is_some_and(|policy| {
+                attempts < policy.max_attempts
+                    && (policy.retryable)(&result)
+                    && !CancellationToken::current().is_some_and(|token| token.is_cancelled())
+            });
+            if !retry {
+                return ControlFlow::Continue(Retried { attempts, result });
+            }
+            if let Some(policy) = &self.policy
+                && !wait(policy.delay(attempts))
+            {
+                return ControlFlow::Continue(Retried { attempts, result });
+            }
+            attempts += 1;
+        }
+    }
+    fn priority(&self) -> Priority {
+        self.req.priority()
+    }
+    fn key(&self) -> Option<u64> {
+        self.req.key()
+    }
+    fn kind(&self) -> Option<u64> {
+        self.req.kind()
+    }
+    fn cost(&self) -> u64 {
+        self.req.cost()
+    }
+}
+
+/// Sleep for `delay`, returning false as soon as the request is cancelled
+fn wait(delay: Duration) -> bool {
+    let token = CancellationToken::current();
+    let until = Instant::now() + delay;
+    loop {
+        if token.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return false;
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        std::thread::sleep(left.min(CANCEL_POLL));
+    }
+}
+
+impl<Req, S> StopRunner<Retry<Req>> for S
+where
+    Req: ControlExecuteMessage + Clone,
+    S: StopRunner<Req>,
+{
+    fn get(&self) -> Retry<Req> {
+        Retry::once(StopRunner::<Req>::get(self))
+    }
+}
@@ -1,7 +1,293 @@
+use std::fmt::Display;
 use std::ops::ControlFlow;
-pub trait ControlExecuteMessage: Send + Sync + 'static {
+use std::time::{Duration, Instant};
+
+use std::sync::Arc;
+
+use crate::cancel::{CancelHandle, CancellationToken};
+use crate::layer::Layers;
+use crate::priority::Priority;
+use crate::timeline::{Recorder, Recording};
+use crate::trace::RequestSpan;
+pub trait ControlExecuteMessage: Send + Sync {
     type Res;
     fn execute(self) -> ControlFlow<(), Self::Res>;
+    /// Priority used by runners and the pool dispatcher when this request waits in a queue
+    fn priority(&self) -> Priority {
+        Priority::default()
+    }
+    /// Groups requests for per key policies, like the pool's per key rate limits
+    fn key(&self) -> Option<u64> {
+        None
+    }
+    /// Kind of work the request needs, pools only dispatch it to runners that handle it, see
+    /// [`RunnerSpec::kinds`](crate::pool::RunnerSpec::kinds)
+    fn kind(&self) -> Option<u64> {
+        None
+    }
+    /// Estimated work of the request relative to others, pools balance the summed cost of their
+    /// runners' requests rather than how many they have
+    fn cost(&self) -> u64 {
+        1
+    }
+    /// Execute the requests a batching runner took at once, returning one [`ControlFlow`] per
+    /// request in order, the runner stops at the first [`ControlFlow::Break`]
+    fn execute_batch(batch: Vec<Self>) -> Vec<ControlFlow<(), Self::Res>>
+    where
+        Self: Sized,
+    {
+        batch.into_iter().map(Self::execute).collect()
+    }
+}
+
+/// Makes runners take up to `max` queued requests at once, waiting up to `wait` for them,
+/// and execute them with [`ControlExecuteMessage::execute_batch`]
+///
+/// Batching runners don't take [`Layers`], layers wrap one request at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batching {
+    pub max: usize,
+    pub wait: Duration,
+}
+
+impl Batching {
+    pub fn new(max: usize, wait: Duration) -> Self {
+        Self {
+            max: max.max(1),
+            wait,
+        }
+    }
+}
+
+/// Reported instead of a response when a request's deadline passed before it was executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Deadline exceeded before execution")
+    }
+}
+impl std::error::Error for DeadlineExceeded {}
+
+impl<T, E> From<DeadlineExceeded> for Result<T, E>
+where
+    E: From<DeadlineExceeded>,
+{
+    fn from(e: DeadlineExceeded) -> Self {
+        Err(e.into())
+    }
+}
+
+/// Message a request panicked with, empty if it isn't a string
+pub(crate) fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| (*s).to_owned())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+/// Makes the response reported for a request that missed it's deadline
+type Expired<Req> = fn(DeadlineExceeded) -> Ret<Req>;
+
+/// A request together with the scheduling data runners need for it
+#[derive(Debug)]
+pub(crate) struct Envelope<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    req: Req,
+    priority: Priority,
+    deadline: Option<(Instant, Expired<Req>)>,
+    token: CancellationToken,
+    /// Layers of the runner the request was sent to
+    layers: Option<Arc<Layers<Req>>>,
+    enqueued: Instant,
+    span: RequestSpan,
+    recording: Option<Box<Recording>>,
+}
+
+impl<Req> Envelope<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    pub(crate) fn new(req: Req) -> Self {
+        let priority = req.priority();
+        Self::with_priority(req, priority)
+    }
+    pub(crate) fn with_priority(req: Req, priority: Priority) -> Self {
+        Self {
+            req,
+            priority,
+            deadline: None,
+            token: CancellationToken::new(),
+            layers: None,
+            enqueued: Instant::now(),
+            span: RequestSpan::default(),
+            recording: None,
+        }
+    }
+    /// Execute the request through `layers`
+    pub(crate) fn layered(mut self, layers: Option<&Arc<Layers<Req>>>) -> Self {
+        self.layers = layers.cloned();
+        self
+    }
+    /// Record the request's timeline from now on with `recorder`
+    pub(crate) fn recorded(mut self, recorder: Option<&Recorder>) -> Self {
+        self.recording = recorder.map(Recording::enqueue);
+        self
+    }
+    /// A pool handed the request to a runner
+    pub(crate) fn dispatched(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.dispatch();
+        }
+    }
+    /// Trace the request in `span`
+    pub(crate) fn traced(mut self, span: RequestSpan) -> Self {
+        self.span = span;
+        self
+    }
+    pub(crate) fn span(&self) -> &RequestSpan {
+        &self.span
+    }
+    pub(crate) fn take_span(&mut self) -> RequestSpan {
+        std::mem::take(&mut self.span)
+    }
+    /// Wraps a request that's skipped and answered with [`DeadlineExceeded`] if it's not
+    /// executed before `deadline`
+    pub(crate) fn with_deadline(req: Req, deadline: Instant) -> Self
+    where
+        Ret<Req>: From<DeadlineExceeded>,
+    {
+        Self {
+            deadline: Some((deadline, Ret::<Req>::from)),
+            ..Self::new(req)
+        }
+    }
+    /// Wraps a stop request so it's taken after every request queued before it
+    pub(crate) fn stop(req: Req) -> Self {
+        Self::with_priority(req, Priority::MIN)
+    }
+    pub(crate) fn into_inner(self) -> Req {
+        self.req
+    }
+    pub(crate) fn handle(&self) -> CancelHandle {
+        CancelHandle::new(self.token.clone())
+    }
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+    /// When the request was sent
+    pub(crate) fn enqueued(&self) -> Instant {
+        self.enqueued
+    }
+}
+
+impl<Req> ControlExecuteMessage for Envelope<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    /// `None` when the request was cancelled before it started
+    type Res = Option<Ret<Req>>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let Envelope {
+            req,
+            deadline,
+            token,
+            layers,
+            mut recording,
+            ..
+        } = self;
+        if !token.start() {
+            return ControlFlow::Continue(None);
+        }
+        if let Some(recording) = &mut recording {
+            recording.start();
+        }
+        let _end = recording.map(Recorded);
+        token.run(|| match deadline {
+            Some((deadline, expired)) if Instant::now() > deadline => {
+                ControlFlow::Continue(Some(expired(DeadlineExceeded)))
+            }
+            _ => {
+                let res = match layers {
+                    Some(layers) => layers.execute(req),
+                    None => req.execute(),
+                };
+                match res {
+                    ControlFlow::Continue(v) => ControlFlow::Continue(Some(v)),
+                    ControlFlow::Break(()) => ControlFlow::Break(()),
+                }
+            }
+        })
+    }
+    fn priority(&self) -> Priority {
+        self.priority
+    }
+    fn key(&self) -> Option<u64> {
+        self.req.key()
+    }
+    fn kind(&self) -> Option<u64> {
+        self.req.kind()
+    }
+    fn cost(&self) -> u64 {
+        self.req.cost()
+    }
+    fn execute_batch(batch: Vec<Self>) -> Vec<ControlFlow<(), Self::Res>> {
+        let now = Instant::now();
+        // Responses of skipped requests, `None` for the ones given to the batch
+        let mut skipped = Vec::with_capacity(batch.len());
+        let mut reqs = Vec::with_capacity(batch.len());
+        let mut tokens = Vec::with_capacity(batch.len());
+        let mut recordings = Vec::new();
+        for mut env in batch {
+            if !env.token.start() {
+                skipped.push(Some(None));
+                continue;
+            }
+            if let Some(mut recording) = env.recording.take() {
+                recording.start();
+                recordings.push(Recorded(recording));
+            }
+            match env.deadline {
+                Some((deadline, expired)) if now > deadline => {
+                    env.token.finish();
+                    skipped.push(Some(Some(expired(DeadlineExceeded))));
+                }
+                _ => {
+                    skipped.push(None);
+                    reqs.push(env.req);
+                    tokens.push(env.token);
+                }
+            }
+        }
+        // Batching runners have no layers
+        let mut executed = Req::execute_batch(reqs).into_iter();
+        for token in tokens {
+            token.finish();
+        }
+        drop(recordings);
+        skipped
+            .into_iter()
+            .map(|skipped| match skipped {
+                Some(res) => ControlFlow::Continue(res),
+                None => match executed.next() {
+                    Some(ControlFlow::Continue(v)) => ControlFlow::Continue(Some(v)),
+                    Some(ControlFlow::Break(())) | None => ControlFlow::Break(()),
+                },
+            })
+            .collect()
+    }
+}
+
+/// Records the end of a request's execution when dropped, even by a panic
+struct Recorded(Box<Recording>);
+
+impl Drop for Recorded {
+    fn drop(&mut self) {
+        self.0.end();
+    }
 }
 
 /// Makes a request that a runner's [`ControlExecuteMessage`] can identify and return a [`ControlFlow::Break`]
@@ -0,0 +1,309 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use tower::Service;
+
+use crate::oneshot::RunnerApi;
+use crate::runner::{ControlExecuteMessage, Ret, RunnerApi as _, StopRunner};
+
+/// Why a request sent through a [`tower::Service`] adapter got no response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceError {
+    /// The runner is stopped
+    Closed,
+    /// The request was dropped before it was answered
+    Dropped,
+    /// The request panicked
+    Panicked,
+    /// No runner handles the request's [`kind`](ControlExecuteMessage::kind)
+    Unhandled,
+}
+impl Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceError::Closed => write!(f, "Runner closed"),
+            ServiceError::Dropped => write!(f, "Request dropped before it was answered"),
+            ServiceError::Panicked => write!(f, "Request panicked"),
+            ServiceError::Unhandled => write!(f, "No runner handles the request's kind"),
+        }
+    }
+}
+impl std::error::Error for ServiceError {}
+
+#[derive(Debug, Default)]
+struct CapacityState {
+    in_flight: usize,
+    waiting: Vec<Waker>,
+}
+
+/// Requests of an adapter that may wait for their response at once
+#[derive(Debug)]
+pub(crate) struct Capacity {
+    max: usize,
+    state: Mutex<CapacityState>,
+}
+
+impl Capacity {
+    pub(crate) fn new(max: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max: max.max(1),
+            state: Mutex::default(),
+        })
+    }
+    pub(crate) fn poll_acquire(self: &Arc<Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight < self.max {
+            state.in_flight += 1;
+            return Poll::Ready(Permit(self.clone()));
+        }
+        state.waiting.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Place of one request in it's adapter's [`Capacity`], given back when dropped
+#[derive(Debug)]
+pub(crate) struct Permit(Arc<Capacity>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let waiting = {
+            let mut state = self.0.state.lock().unwrap();
+            state.in_flight -= 1;
+            std::mem::take(&mut state.waiting)
+        };
+        waiting.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Channel a [`ResponseFuture`] waits on
+#[derive(Debug)]
+enum Waiting<T> {
+    /// Gets the response, or nothing when the request is dropped
+    Response(oneshot::Receiver<T>),
+    /// Gets the response or why there's none
+    Outcome(oneshot::Receiver<Result<T, ServiceError>>),
+}
+
+/// Withdraws a request whose future is dropped, if it wasn't answered yet
+type Abandon = Box<dyn FnOnce() + Send + Sync>;
+
+/// Response of a request sent through a [`tower::Service`] adapter
+///
+/// Dropping it gives it's place back to the adapter, and withdraws the request from a pool if
+/// it wasn't answered yet
+pub struct ResponseFuture<T> {
+    /// `None` when the request couldn't be sent
+    recv: Option<Waiting<T>>,
+    abandon: Option<Abandon>,
+    _permit: Permit,
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for ResponseFuture<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseFuture")
+            .field("recv", &self.recv)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> ResponseFuture<T> {
+    pub(crate) fn new(recv: Option<oneshot::Receiver<T>>, permit: Permit) -> Self {
+        Self {
+            recv: recv.map(Waiting::Response),
+            abandon: None,
+            _permit: permit,
+        }
+    }
+    /// Future resolved with whatever is sent on `recv`, calling `abandon` when dropped
+    pub(crate) fn outcome(
+        recv: Option<oneshot::Receiver<Result<T, ServiceError>>>,
+        abandon: Option<Abandon>,
+        permit: Permit,
+    ) -> Self {
+        Self {
+            recv: recv.map(Waiting::Outcome),
+            abandon,
+            _permit: permit,
+        }
+    }
+}
+
+impl<T> Drop for ResponseFuture<T> {
+    fn drop(&mut self) {
+        if let Some(abandon) = self.abandon.take() {
+            abandon();
+        }
+    }
+}
+
+impl<T> Future for ResponseFuture<T> {
+    type Output = Result<T, ServiceError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.recv {
+            Some(Waiting::Response(recv)) => Pin::new(recv)
+                .poll(cx)
+                .map(|res| res.map_err(|_| ServiceError::Dropped)),
+            Some(Waiting::Outcome(recv)) => Pin::new(recv)
+                .poll(cx)
+                .map(|res| res.unwrap_or(Err(ServiceError::Dropped))),
+            None => Poll::Ready(Err(ServiceError::Closed)),
+        }
+    }
+}
+
+/// [`tower::Service`] sending requests to a oneshot runner, at most `capacity` of them waiting
+/// for their response at once
+pub struct RunnerService<Req>
+where
+    Req: ControlExecuteMessage,
+{
+    runner: RunnerApi<Req>,
+    capacity: Arc<Capacity>,
+    permit: Option<Permit>,
+}
+
+impl<Req> RunnerService<Req>
+where
+    Req: ControlExecuteMessage + 'static,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    pub fn new(runner: RunnerApi<Req>, capacity: usize) -> Self {
+        Self {
+            runner,
+            capacity: Capacity::new(capacity),
+            permit: None,
+        }
+    }
+    /// Take the runner back, to close it
+    pub fn into_inner(self) -> RunnerApi<Req> {
+        self.runner
+    }
+}
+
+impl<Req> Service<Req> for RunnerService<Req>
+where
+    Req: ControlExecuteMessage + 'static,
+    Ret<Req>: std::fmt::Debug + Send + 'static,
+{
+    type Response = Ret<Req>;
+    type Error = ServiceError;
+    type Future = ResponseFuture<Ret<Req>>;
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.permit.is_none() {
+            self.permit = Some(std::task::ready!(self.capacity.poll_acquire(cx)));
+        }
+        Poll::Ready(Ok(()))
+    }
+    fn call(&mut self, req: Req) -> Self::Future {
+        let permit = self
+            .permit
+            .take()
+            .expect("poll_ready must be called before call");
+        let recv = self.runner.send(req).ok().map(|res| res.into_receiver());
+        ResponseFuture::new(recv, permit)
+    }
+}
+
+/// Wakes the thread blocked on a future
+struct Unpark(std::thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Block the current thread until `f` is ready
+fn block_on<F>(f: F) -> F::Output
+where
+    F: Future,
+{
+    let mut f = std::pin::pin!(f);
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+            return v;
+        }
+        std::thread::park();
+    }
+}
+
+/// Shares a [`tower::Service`] between runners, which drive it with the [`Driven`] requests
+/// it makes, and stops them
+#[derive(Debug)]
+pub struct Driver<S> {
+    service: Arc<Mutex<S>>,
+}
+
+impl<S> Clone for Driver<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+        }
+    }
+}
+
+impl<S> Driver<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(Mutex::new(service)),
+        }
+    }
+    /// Request that calls the service with `req`
+    pub fn call<R>(&self, req: R) -> Driven<S, R> {
+        Driven {
+            service: self.service.clone(),
+            req: Some(req),
+        }
+    }
+}
+
+/// Request executed by calling a [`tower::Service`], blocking the runner until it responds
+///
+/// The service is locked only while it's polled for readiness and called, so several runners
+/// may wait for it's responses at once
+#[derive(Debug)]
+pub struct Driven<S, R> {
+    service: Arc<Mutex<S>>,
+    /// `None` stops the runner
+    req: Option<R>,
+}
+
+impl<S, R> ControlExecuteMessage for Driven<S, R>
+where
+    S: Service<R> + Send,
+    R: Send + Sync,
+{
+    type Res = Result<S::Response, S::Error>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let Some(req) = self.req else {
+            return ControlFlow::Break(());
+        };
+        let call = {
+            let mut service = self.service.lock().unwrap();
+            match block_on(std::future::poll_fn(|cx| service.poll_ready(cx))) {
+                Ok(()) => Ok(service.call(req)),
+                Err(e) => Err(e),
+            }
+        };
+        ControlFlow::Continue(match call {
+            Ok(call) => block_on(call),
+            Err(e) => Err(e),
+        })
+    }
+}
+
+impl<S, R> StopRunner<Driven<S, R>> for Driver<S> {
+    fn get(&self) -> Driven<S, R> {
+        Driven {
+            service: self.service.clone(),
+            req: None,
+        }
+    }
+}
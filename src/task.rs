@@ -0,0 +1,115 @@
+//! Requests of the runners behind pipelines and dags
+
+use crate::priority::Priority;
+use crate::runner::{ControlExecuteMessage, StopRunner, panic_message};
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+
+/// Why a [`Task`] has no result
+#[derive(Debug)]
+pub(crate) enum Failure<E, P> {
+    /// The request returned an error
+    Failed(E),
+    Panicked(String),
+    /// The request returned [`ControlFlow::Break`]
+    Stopped,
+    /// Nothing was executed, see [`Task::pass`]
+    Passed(P),
+}
+
+enum Work<Req, P> {
+    Run(Req),
+    Pass(P),
+    Stop,
+}
+
+/// Request executing `Req` without letting it's panics or stops reach the runner
+pub(crate) struct Task<Req, P> {
+    work: Work<Req, P>,
+    priority: Priority,
+}
+
+impl<Req, P> Task<Req, P>
+where
+    Req: ControlExecuteMessage,
+{
+    /// Task executing `req`, queued at it's priority
+    pub(crate) fn run(req: Req) -> Self {
+        let priority = req.priority();
+        Self {
+            work: Work::Run(req),
+            priority,
+        }
+    }
+    /// Task answered with `passed` as it is, in it's turn
+    pub(crate) fn pass(passed: P) -> Self {
+        Self {
+            work: Work::Pass(passed),
+            priority: Priority::default(),
+        }
+    }
+    /// Queue the task at the default priority whatever `Req`'s is, so tasks keep their order
+    pub(crate) fn in_order(mut self) -> Self {
+        self.priority = Priority::default();
+        self
+    }
+}
+
+impl<Req, T, E, P> ControlExecuteMessage for Task<Req, P>
+where
+    Req: ControlExecuteMessage<Res = Result<T, E>>,
+    P: Send + Sync,
+{
+    type Res = Result<T, Failure<E, P>>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let req = match self.work {
+            Work::Run(req) => req,
+            Work::Pass(passed) => return ControlFlow::Continue(Err(Failure::Passed(passed))),
+            Work::Stop => return ControlFlow::Break(()),
+        };
+        ControlFlow::Continue(
+            match std::panic::catch_unwind(AssertUnwindSafe(|| req.execute())) {
+                Ok(ControlFlow::Continue(res)) => res.map_err(Failure::Failed),
+                Ok(ControlFlow::Break(())) => Err(Failure::Stopped),
+                Err(panic) => Err(Failure::Panicked(panic_message(&*panic))),
+            },
+        )
+    }
+    fn priority(&self) -> Priority {
+        self.priority
+    }
+    fn key(&self) -> Option<u64> {
+        match &self.work {
+            Work::Run(req) => req.key(),
+            _ => None,
+        }
+    }
+    fn kind(&self) -> Option<u64> {
+        match &self.work {
+            Work::Run(req) => req.kind(),
+            _ => None,
+        }
+    }
+    fn cost(&self) -> u64 {
+        match &self.work {
+            Work::Run(req) => req.cost(),
+            _ => 1,
+        }
+    }
+}
+
+/// Stops the runners of [`Task`]s
+pub(crate) struct StopTask;
+
+impl<Req, T, E, P> StopRunner<Task<Req, P>> for StopTask
+where
+    Req: ControlExecuteMessage<Res = Result<T, E>>,
+    P: Send + Sync,
+{
+    fn get(&self) -> Task<Req, P> {
+        Task {
+            work: Work::Stop,
+            priority: Priority::default(),
+        }
+    }
+}
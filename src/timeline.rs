@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Sent by the caller
+    Enqueue,
+    /// Handed to a runner by a pool, or taken by a queue or oneshot runner
+    Dispatch,
+    Start,
+    End,
+}
+
+#[derive(Debug)]
+struct Event {
+    id: u64,
+    phase: Phase,
+    at: Duration,
+    thread: ThreadId,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    events: Vec<Event>,
+    /// Every thread that recorded an event, in the order they were seen, with their name
+    threads: Vec<(ThreadId, Option<String>)>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    start: Instant,
+    next_id: AtomicU64,
+    state: Mutex<State>,
+}
+
+/// Records when requests are sent, dispatched, started and finished, to export the timeline
+/// of every runner it's given to
+///
+/// Clones record to the same timeline, so one recorder may follow several runners and pools
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    inner: Arc<Inner>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                start: Instant::now(),
+                next_id: AtomicU64::new(0),
+                state: Mutex::default(),
+            }),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn record(&self, id: u64, phase: Phase) {
+        let at = self.inner.start.elapsed();
+        let thread = std::thread::current();
+        let mut state = self.inner.state.lock().unwrap();
+        if !state.threads.iter().any(|(id, _)| *id == thread.id()) {
+            state
+                .threads
+                .push((thread.id(), thread.name().map(str::to_owned)));
+        }
+        state.events.push(Event {
+            id,
+            phase,
+            at,
+            thread: thread.id(),
+        });
+    }
+    /// Forget everything recorded so far
+    pub fn clear(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.events.clear();
+        state.threads.clear();
+    }
+    /// Chrome Trace Event JSON of the timeline, for `chrome://tracing` or Perfetto
+    ///
+    /// Every thread gets it's own track, the ones that executed requests are named `runner N`.
+    /// Requests are slices on the runner that executed them, linked by a flow to their
+    /// enqueue and dispatch on the threads that sent and dispatched them
+    pub fn chrome_trace(&self) -> String {
+        let state = self.inner.state.lock().unwrap();
+        let runs = |thread: &ThreadId| {
+            state
+                .events
+                .iter()
+                .any(|e| e.phase == Phase::Start && e.thread == *thread)
+        };
+        let mut tracks = HashMap::new();
+        let mut out = String::from("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[");
+        out.push_str(
+            "{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{\"name\":\"a-run\"}}",
+        );
+        let (mut runners, mut others) = (0, 0);
+        for (tid, (thread, name)) in state.threads.iter().enumerate() {
+            tracks.insert(*thread, tid);
+            let track = if runs(thread) {
+                runners += 1;
+                format!("runner {}", runners - 1)
+            } else if let Some(name) = name {
+                name.clone()
+            } else {
+                others += 1;
+                format!("thread {}", others - 1)
+            };
+            let _ = write!(
+                out,
+                ",{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{tid},\
+                 \"args\":{{\"name\":\"{}\"}}}}",
+                escape(&track)
+            );
+        }
+        // Events of each request, in the order they were recorded
+        let mut requests: HashMap<u64, Vec<&Event>> = HashMap::new();
+        for event in &state.events {
+            requests.entry(event.id).or_default().push(event);
+        }
+        let mut ids: Vec<_> = requests.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let events = &requests[&id];
+            let find = |phase| events.iter().find(|e| e.phase == phase);
+            let executed = find(Phase::Start).is_some();
+            let enqueued = find(Phase::Enqueue).map(|e| e.at);
+            let mut flow = "s";
+            for event in events {
+                let tid = tracks[&event.thread];
+                let ts = micros(event.at);
+                let (name, dur, args) = match event.phase {
+                    Phase::Enqueue => ("enqueue", 0.0, String::new()),
+                    Phase::Dispatch => ("dispatch", 0.0, String::new()),
+                    Phase::Start => {
+                        let end = find(Phase::End).map_or(event.at, |e| e.at);
+                        let waited = enqueued
+                            .map_or(0.0, |enqueued| micros(event.at.saturating_sub(enqueued)));
+                        (
+                            "execute",
+                            micros(end - event.at),
+                            format!(",\"waited_us\":{waited:.3}"),
+                        )
+                    }
+                    Phase::End => continue,
+                };
+                let _ = write!(
+                    out,
+                    ",{{\"name\":\"{name}\",\"cat\":\"request\",\"ph\":\"X\",\"pid\":1,\
+                     \"tid\":{tid},\"ts\":{ts:.3},\"dur\":{dur:.3},\"args\":{{\"id\":{id}{args}}}}}"
+                );
+                // The flow starts at the first event and ends at the execution
+                if executed && events.len() > 1 {
+                    let bind = if event.phase == Phase::Start {
+                        flow = "f";
+                        ",\"bp\":\"e\""
+                    } else {
+                        ""
+                    };
+                    let _ = write!(
+                        out,
+                        ",{{\"name\":\"request\",\"cat\":\"request\",\"ph\":\"{flow}\",\
+                         \"id\":{id},\"pid\":1,\"tid\":{tid},\"ts\":{ts:.3}{bind}}}"
+                    );
+                    flow = "t";
+                }
+            }
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+fn micros(d: Duration) -> f64 {
+    d.as_secs_f64() * 1e6
+}
+
+/// Escape a JSON string
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A request being recorded, carried along with it
+#[derive(Debug)]
+pub(crate) struct Recording {
+    recorder: Recorder,
+    id: u64,
+    dispatched: bool,
+}
+
+impl Recording {
+    /// Start recording a request sent from the current thread
+    pub(crate) fn enqueue(recorder: &Recorder) -> Box<Self> {
+        let id = recorder.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        recorder.record(id, Phase::Enqueue);
+        Box::new(Self {
+            recorder: recorder.clone(),
+            id,
+            dispatched: false,
+        })
+    }
+    pub(crate) fn dispatch(&mut self) {
+        self.dispatched = true;
+        self.recorder.record(self.id, Phase::Dispatch);
+    }
+    /// The request starts executing, it was dispatched now unless a pool dispatched it before
+    pub(crate) fn start(&mut self) {
+        if !self.dispatched {
+            self.dispatch();
+        }
+        self.recorder.record(self.id, Phase::Start);
+    }
+    pub(crate) fn end(&self) {
+        self.recorder.record(self.id, Phase::End);
+    }
+}
@@ -0,0 +1,106 @@
+//! `tracing` spans and events of pooled requests, compiled to nothing without the `tracing`
+//! feature
+
+use std::time::Duration;
+
+#[cfg(feature = "tracing")]
+use tracing::{Level, Span, debug, event, info_span, span::EnteredSpan};
+
+/// Span of a request sent to a pool, a child of the sender's span
+///
+/// The runner executes the request inside it, so whatever the request logs nests under the
+/// sender's span
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestSpan {
+    /// Boxed to keep requests small, `None` when no subscriber is interested
+    #[cfg(feature = "tracing")]
+    span: Option<Box<Span>>,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl RequestSpan {
+    pub(crate) fn new(seq: usize, client: usize) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::debug_span!(target: "a_run::pool", "request", seq, client);
+            Self {
+                span: (!span.is_disabled()).then(|| Box::new(span)),
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        Self {}
+    }
+    /// The manager queued the request behind `queued` others
+    pub(crate) fn enqueued(&self, queued: usize) {
+        #[cfg(feature = "tracing")]
+        if let Some(span) = &self.span {
+            debug!(target: "a_run::pool", parent: &**span, queued, "enqueued");
+        }
+    }
+    /// The balancer picked `runner`, which had `load` requests
+    pub(crate) fn dispatched(&self, runner: usize, load: usize) {
+        #[cfg(feature = "tracing")]
+        if let Some(span) = &self.span {
+            debug!(target: "a_run::pool", parent: &**span, runner, load, "dispatched");
+        }
+    }
+    pub(crate) fn started(&self, runner: usize) {
+        #[cfg(feature = "tracing")]
+        if let Some(span) = &self.span {
+            debug!(target: "a_run::pool", parent: &**span, runner, "execution started");
+        }
+    }
+    pub(crate) fn finished(&self, runner: usize, took: Duration, panicked: bool) {
+        #[cfg(feature = "tracing")]
+        if let Some(span) = &self.span {
+            if panicked {
+                event!(target: "a_run::pool", parent: &**span, Level::WARN, runner, ?took, "execution panicked");
+            } else {
+                debug!(target: "a_run::pool", parent: &**span, runner, ?took, "execution finished");
+            }
+        }
+    }
+    /// Run `f` inside the span
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        if let Some(span) = &self.span {
+            return span.in_scope(f);
+        }
+        f()
+    }
+}
+
+/// Span of a phase of closing a pool, entered until dropped
+pub(crate) struct Phase {
+    #[cfg(feature = "tracing")]
+    _span: EnteredSpan,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl Phase {
+    /// Waiting for the `queued` and `in_flight` requests to finish
+    pub(crate) fn close(queued: usize, in_flight: usize) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            _span: info_span!(target: "a_run::pool", "close", queued, in_flight).entered(),
+        }
+    }
+    /// Stopping the `runners`
+    pub(crate) fn kill(runners: usize) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            _span: info_span!(target: "a_run::pool", "kill", runners).entered(),
+        }
+    }
+    pub(crate) fn runner_stopped(&self, runner: usize) {
+        #[cfg(feature = "tracing")]
+        debug!(target: "a_run::pool", runner, "runner stopped");
+    }
+}
+
+/// The pool's manager stopped taking requests, with `queued` of them not dispatched yet
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn stopping(queued: usize) {
+    #[cfg(feature = "tracing")]
+    debug!(target: "a_run::pool", queued, "stopping");
+}
@@ -0,0 +1,72 @@
+use a_run::pool::Pool;
+use a_run::queue::RunnerApi;
+use a_run::runner::{Batching, ControlExecuteMessage, RunnerApi as _};
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request answered with it's id and the size of the batch it was executed in
+struct Job(u32);
+
+impl ControlExecuteMessage for Job {
+    type Res = (u32, usize);
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        ControlFlow::Continue((self.0, 1))
+    }
+    fn execute_batch(batch: Vec<Self>) -> Vec<ControlFlow<(), Self::Res>> {
+        let len = batch.len();
+        batch
+            .into_iter()
+            .map(|job| ControlFlow::Continue((job.0, len)))
+            .collect()
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+const BATCHING: Batching = Batching {
+    max: 4,
+    wait: Duration::from_millis(50),
+};
+
+#[test]
+fn runners_execute_requests_in_batches() {
+    let responses = within(|| {
+        let runner = RunnerApi::<Job>::with_batching(BATCHING);
+        for id in 0..8 {
+            runner.send(Job(id)).unwrap();
+        }
+        (0..8).map(|_| runner.recv().unwrap()).collect::<Vec<_>>()
+    });
+    assert_eq!(responses, (0..8).map(|id| (id, 4)).collect::<Vec<_>>());
+}
+
+#[test]
+fn a_batch_is_executed_once_it_waited_long_enough() {
+    let response = within(|| {
+        let runner = RunnerApi::<Job>::with_batching(BATCHING);
+        runner.send(Job(0)).unwrap();
+        runner.recv().unwrap()
+    });
+    assert_eq!(response, (0, 1));
+}
+
+#[test]
+fn pool_runners_execute_requests_in_batches() {
+    let responses = within(|| {
+        let pool = Pool::<Job>::new(1).batching(BATCHING).start();
+        for id in 0..8 {
+            pool.send(Job(id)).unwrap();
+        }
+        (0..8).map(|_| pool.recv().unwrap()).collect::<Vec<_>>()
+    });
+    assert_eq!(responses, (0..8).map(|id| (id, 4)).collect::<Vec<_>>());
+}
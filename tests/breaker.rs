@@ -0,0 +1,62 @@
+use a_run::breaker::{CircuitBreaker, CircuitState};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Request failing with `Err`, or panicking
+struct Probe {
+    panics: bool,
+}
+
+impl ControlExecuteMessage for Probe {
+    type Res = Result<(), ()>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        assert!(!self.panics, "probe panicked");
+        ControlFlow::Continue(Err(()))
+    }
+}
+
+fn breaker() -> Arc<CircuitBreaker<Result<(), ()>>> {
+    Arc::new(
+        CircuitBreaker::new(Result::is_err)
+            .threshold(1.0, 1)
+            .open_for(Duration::from_millis(20)),
+    )
+}
+
+#[test]
+fn panicking_probe_reopens_the_circuit() {
+    let breaker = breaker();
+    let _ = breaker.guard(Probe { panics: false }).unwrap().execute();
+    assert_eq!(breaker.state(), CircuitState::Open);
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    let probe = breaker.guard(Probe { panics: true }).unwrap();
+    assert!(std::panic::catch_unwind(AssertUnwindSafe(|| probe.execute())).is_err());
+    assert_eq!(breaker.state(), CircuitState::Open);
+    // The probe slot isn't held forever
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(breaker.guard(Probe { panics: false }).is_ok());
+}
+
+#[test]
+fn panicking_request_counts_as_failure() {
+    let breaker = breaker();
+    let req = breaker.guard(Probe { panics: true }).unwrap();
+    assert!(std::panic::catch_unwind(AssertUnwindSafe(|| req.execute())).is_err());
+    assert_eq!(breaker.state(), CircuitState::Open);
+}
+
+#[test]
+#[should_panic(expected = "failure rate must be in (0, 1]")]
+fn zero_failure_rate_is_rejected() {
+    let _ = CircuitBreaker::<Result<(), ()>>::new(Result::is_err).threshold(0.0, 10);
+}
+
+#[test]
+#[should_panic(expected = "failure rate must be in (0, 1]")]
+fn nan_failure_rate_is_rejected() {
+    let _ = CircuitBreaker::<Result<(), ()>>::new(Result::is_err).threshold(f64::NAN, 10);
+}
@@ -0,0 +1,48 @@
+use a_run::pool::Pool;
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+/// Request returning the runner it was made for
+struct Job(usize);
+
+impl ControlExecuteMessage for Job {
+    type Res = usize;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        ControlFlow::Continue(self.0)
+    }
+}
+
+#[test]
+fn paused_pool_holds_broadcasts_back() {
+    let pool = Pool::<Job>::new(2).start();
+    pool.pause();
+    let broadcast = pool.broadcast(Job).unwrap();
+    assert_eq!(
+        broadcast.recv_timeout(Duration::from_millis(50)),
+        Err(RecvTimeoutError::Timeout)
+    );
+    pool.resume();
+    assert_eq!(broadcast.recv().unwrap(), [Some(0), Some(1)]);
+}
+
+#[test]
+fn panicking_make_req_fails_the_broadcast_only() {
+    let pool = Pool::<Job>::new(2).start();
+    let broadcast = pool
+        .broadcast(|runner| {
+            assert_ne!(runner, 1, "no request for runner 1");
+            Job(runner)
+        })
+        .unwrap();
+    assert!(std::panic::catch_unwind(AssertUnwindSafe(|| broadcast.recv())).is_err());
+    // The pool still works
+    pool.send(Job(7)).unwrap();
+    assert_eq!(pool.recv().unwrap(), 7);
+    assert_eq!(
+        pool.broadcast(Job).unwrap().recv().unwrap(),
+        [Some(0), Some(1)]
+    );
+}
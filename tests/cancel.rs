@@ -0,0 +1,83 @@
+use a_run::cancel::{Cancellation, CancellationToken};
+use a_run::pool::Pool;
+use a_run::queue::RunnerApi;
+use a_run::runner::{ControlExecuteMessage, RunnerApi as _};
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Request returning it's id after sleeping for a while, or whether it was cancelled if it
+/// noticed it first
+struct Job(u32, Duration);
+
+impl ControlExecuteMessage for Job {
+    type Res = (u32, bool);
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let token = CancellationToken::current().unwrap();
+        let until = Instant::now() + self.1;
+        while Instant::now() < until {
+            if token.is_cancelled() {
+                return ControlFlow::Continue((self.0, true));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        ControlFlow::Continue((self.0, false))
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+#[test]
+fn runners_drop_requests_cancelled_before_they_start() {
+    let (cancellation, responses) = within(|| {
+        let runner = RunnerApi::<Job>::new();
+        runner.send(Job(0, Duration::from_millis(50))).unwrap();
+        let queued = runner.send(Job(1, Duration::ZERO)).unwrap();
+        runner.send(Job(2, Duration::ZERO)).unwrap();
+        let cancellation = queued.cancel();
+        let responses: Vec<_> = (0..2).map(|_| runner.recv().unwrap()).collect();
+        (cancellation, responses)
+    });
+    assert_eq!(cancellation, Cancellation::Dequeued);
+    assert_eq!(responses, [(0, false), (2, false)]);
+}
+
+#[test]
+fn pools_drop_requests_cancelled_before_they_start() {
+    let (cancellation, responses) = within(|| {
+        let pool = Pool::<Job>::new(1).depth(1).start();
+        pool.send(Job(0, Duration::from_millis(50))).unwrap();
+        let queued = pool.send(Job(1, Duration::ZERO)).unwrap();
+        pool.send(Job(2, Duration::ZERO)).unwrap();
+        let cancellation = queued.cancel();
+        let responses: Vec<_> = (0..2).map(|_| pool.recv().unwrap()).collect();
+        (cancellation, responses)
+    });
+    assert_eq!(cancellation, Cancellation::Dequeued);
+    assert_eq!(responses, [(0, false), (2, false)]);
+}
+
+#[test]
+fn running_requests_are_signalled_and_answered() {
+    let (cancellations, response) = within(|| {
+        let pool = Pool::<Job>::new(1).start();
+        let running = pool.send(Job(0, Duration::from_secs(5))).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let signalled = running.cancel();
+        let response = pool.recv().unwrap();
+        ([signalled, running.cancel()], response)
+    });
+    assert_eq!(
+        cancellations,
+        [Cancellation::Signalled, Cancellation::TooLate]
+    );
+    assert_eq!(response, (0, true));
+}
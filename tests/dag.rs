@@ -0,0 +1,67 @@
+use a_run::dag::{Dag, TaskError};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+
+/// Request returning it's number, panicking on zero
+struct Add(u32);
+
+impl ControlExecuteMessage for Add {
+    type Res = Result<u32, ()>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        assert!(self.0 != 0, "zero");
+        ControlFlow::Continue(Ok(self.0))
+    }
+}
+
+#[test]
+fn results_of_dependencies_are_passed_on() {
+    let mut dag = Dag::<Add, u32, ()>::new().runners(3);
+    let a = dag.task(&[], |_| Add(1));
+    let b = dag.task(&[], |_| Add(2));
+    let sum = dag.task(&[a, b], |parents| Add(parents.iter().copied().sum()));
+    let failed = dag.task(&[], |_| Add(0));
+    let skipped = dag.task(&[failed], |_| Add(1));
+    let results = dag.run().unwrap();
+    assert_eq!(results.get(sum), &Ok(3));
+    assert_eq!(
+        results.get(failed),
+        &Err(TaskError::Panicked("zero".into()))
+    );
+    assert_eq!(results.get(skipped), &Err(TaskError::Skipped(failed)));
+}
+
+#[test]
+fn panicking_make_req_fails_it_s_task() {
+    let mut dag = Dag::<Add, u32, ()>::new();
+    let a = dag.task(&[], |_| Add(1));
+    let unmade = dag.task(&[a], |_| panic!("no request"));
+    let skipped = dag.task(&[unmade], |_| Add(1));
+    let other = dag.task(&[a], |parents| Add(*parents[0] + 1));
+    let results = dag.run().unwrap();
+    assert_eq!(
+        results.get(unmade),
+        &Err(TaskError::Panicked("no request".into()))
+    );
+    assert_eq!(results.get(skipped), &Err(TaskError::Skipped(unmade)));
+    assert_eq!(results.get(other), &Ok(2));
+}
+
+#[test]
+#[should_panic(expected = "another dag")]
+fn task_of_another_dag_is_rejected() {
+    let mut other = Dag::<Add, u32, ()>::new();
+    let foreign = other.task(&[], |_| Add(1));
+    let mut dag = Dag::<Add, u32, ()>::new();
+    let task = dag.task(&[], |_| Add(1));
+    dag.depend(task, foreign);
+}
+
+#[test]
+#[should_panic(expected = "another dag")]
+fn results_of_another_dag_are_rejected() {
+    let mut other = Dag::<Add, u32, ()>::new();
+    let foreign = other.task(&[], |_| Add(1));
+    let mut dag = Dag::<Add, u32, ()>::new();
+    dag.task(&[], |_| Add(1));
+    let _ = dag.run().unwrap().get(foreign);
+}
@@ -0,0 +1,76 @@
+use a_run::pool::Pool;
+use a_run::queue::RunnerApi;
+use a_run::runner::{ControlExecuteMessage, DeadlineExceeded, RunnerApi as _};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Request returning it's id after sleeping for a while, counting the ones executed
+struct Job(u32, Duration, Arc<AtomicUsize>);
+
+impl ControlExecuteMessage for Job {
+    type Res = Result<u32, DeadlineExceeded>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        self.2.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(self.1);
+        ControlFlow::Continue(Ok(self.0))
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+/// Send a slow request, then one that expires while it executes and one that doesn't
+fn send_expiring(mut send: impl FnMut(Job, Option<Instant>), executed: &Arc<AtomicUsize>) {
+    let job = |id, ms| Job(id, Duration::from_millis(ms), executed.clone());
+    send(job(0, 50), None);
+    send(job(1, 0), Some(Instant::now() + Duration::from_millis(10)));
+    send(job(2, 0), Some(Instant::now() + Duration::from_secs(5)));
+}
+
+#[test]
+fn runners_skip_requests_past_their_deadline() {
+    let (responses, executed) = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let runner = RunnerApi::<Job>::new();
+        send_expiring(
+            |job, deadline| match deadline {
+                Some(deadline) => drop(runner.send_with_deadline(job, deadline).unwrap()),
+                None => drop(runner.send(job).unwrap()),
+            },
+            &executed,
+        );
+        let responses: Vec<_> = (0..3).map(|_| runner.recv().unwrap()).collect();
+        (responses, executed.load(Ordering::SeqCst))
+    });
+    assert_eq!(responses, [Ok(0), Err(DeadlineExceeded), Ok(2)]);
+    assert_eq!(executed, 2);
+}
+
+#[test]
+fn pools_skip_requests_past_their_deadline() {
+    let (responses, executed) = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::<Job>::new(1).depth(1).start();
+        send_expiring(
+            |job, deadline| match deadline {
+                Some(deadline) => drop(pool.send_with_deadline(job, deadline).unwrap()),
+                None => drop(pool.send(job).unwrap()),
+            },
+            &executed,
+        );
+        let responses: Vec<_> = (0..3).map(|_| pool.recv().unwrap()).collect();
+        (responses, executed.load(Ordering::SeqCst))
+    });
+    assert_eq!(responses, [Ok(0), Err(DeadlineExceeded), Ok(2)]);
+    assert_eq!(executed, 2);
+}
@@ -0,0 +1,92 @@
+use a_run::pool::{Pool, Share};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+/// Request logging who sent it when it executes, after sleeping for a while
+struct Job(char, Duration, Arc<Mutex<Vec<char>>>);
+
+impl ControlExecuteMessage for Job {
+    type Res = ();
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(self.1);
+        self.2.lock().unwrap().push(self.0);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+#[test]
+fn clients_take_turns_by_weight() {
+    let log = within(|| {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let pool = Pool::<Job>::new(1).depth(1).start();
+        let heavy = pool.client(Share::new(2));
+        let light = pool.client(Share::new(1));
+        pool.send(Job('-', Duration::from_millis(50), log.clone()))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        for _ in 0..6 {
+            heavy.send(Job('h', Duration::ZERO, log.clone())).unwrap();
+        }
+        for _ in 0..3 {
+            light.send(Job('l', Duration::ZERO, log.clone())).unwrap();
+        }
+        pool.recv().unwrap();
+        for _ in 0..6 {
+            heavy.recv().unwrap();
+        }
+        for _ in 0..3 {
+            light.recv().unwrap();
+        }
+        Arc::try_unwrap(log).unwrap().into_inner().unwrap()
+    });
+    let order: String = log[1..].iter().collect();
+    assert!(
+        ["hhlhhlhhl", "lhhlhhlhh"].contains(&order.as_str()),
+        "{order}"
+    );
+}
+
+/// Request counting how many of them execute at once
+struct Counted(Arc<(AtomicUsize, AtomicUsize)>);
+
+impl ControlExecuteMessage for Counted {
+    type Res = ();
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let (running, most) = &*self.0;
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        most.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(10));
+        running.fetch_sub(1, Ordering::SeqCst);
+        ControlFlow::Continue(())
+    }
+}
+
+#[test]
+fn clients_have_at_most_max_in_flight_requests_dispatched() {
+    let most = within(|| {
+        let counts = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let pool = Pool::<Counted>::new(4).start();
+        let client = pool.client(Share::default().max_in_flight(2));
+        for _ in 0..8 {
+            client.send(Counted(counts.clone())).unwrap();
+        }
+        for _ in 0..8 {
+            client.recv().unwrap();
+        }
+        counts.1.load(Ordering::SeqCst)
+    });
+    assert_eq!(most, 2);
+}
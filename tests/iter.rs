@@ -0,0 +1,104 @@
+use a_run::pool::Pool;
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request squaring it's number after sleeping that many milliseconds, failing on 13
+struct Square(u64, Arc<AtomicUsize>);
+
+impl ControlExecuteMessage for Square {
+    type Res = Result<u64, u64>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(self.0 % 5));
+        ControlFlow::Continue(if self.0 == 13 {
+            Err(self.0)
+        } else {
+            Ok(self.0 * self.0)
+        })
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+fn squares(range: std::ops::Range<u64>, executed: &Arc<AtomicUsize>) -> Vec<Square> {
+    range.map(|n| Square(n, executed.clone())).collect()
+}
+
+#[test]
+fn map_yields_responses_in_the_order_of_the_requests() {
+    let responses = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let mut pool = Pool::<Square>::new(4).start();
+        pool.map(squares(0..13, &executed))
+            .window(3)
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(responses, (0..13).map(|n| Ok(n * n)).collect::<Vec<_>>());
+}
+
+#[test]
+fn map_unordered_yields_every_response() {
+    let mut responses = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let mut pool = Pool::<Square>::new(4).start();
+        pool.map_unordered(squares(0..13, &executed))
+            .collect::<Vec<_>>()
+    });
+    responses.sort();
+    assert_eq!(responses, (0..13).map(|n| Ok(n * n)).collect::<Vec<_>>());
+}
+
+#[test]
+fn try_map_returns_the_first_error() {
+    let res = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let mut pool = Pool::<Square>::new(2).start();
+        pool.try_map(squares(10..20, &executed))
+    });
+    assert_eq!(res, Err(13));
+}
+
+#[test]
+fn responses_to_other_requests_are_kept_for_recv() {
+    let (mapped, other) = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let mut pool = Pool::<Square>::new(2).start();
+        pool.send(Square(3, executed.clone())).unwrap();
+        let mapped: Vec<_> = pool.map(squares(0..4, &executed)).collect();
+        (mapped, pool.recv().unwrap())
+    });
+    assert_eq!(mapped, [Ok(0), Ok(1), Ok(4), Ok(9)]);
+    assert_eq!(other, Ok(9));
+}
+
+#[test]
+fn dropping_map_cancels_the_requests_not_started() {
+    let (executed, rest) = within(|| {
+        let executed = Arc::new(AtomicUsize::new(0));
+        let mut pool = Pool::<Square>::new(1).start();
+        let first = pool
+            .map(squares(0..40, &executed))
+            .window(40)
+            .next()
+            .unwrap();
+        assert_eq!(first, Ok(0));
+        let executed = executed.load(Ordering::SeqCst);
+        // The pool is still usable
+        let rest: Vec<_> = pool.map(squares(2..4, &Arc::default())).collect();
+        (executed, rest)
+    });
+    assert!(executed < 40, "{executed}");
+    assert_eq!(rest, [Ok(4), Ok(9)]);
+}
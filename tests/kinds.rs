@@ -0,0 +1,73 @@
+use a_run::pool::{Pool, PoolSendError, RunnerSpec, Share};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request of a kind, sleeping for a while
+struct Job(Option<u64>, Duration);
+
+impl ControlExecuteMessage for Job {
+    type Res = Option<u64>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(self.1);
+        ControlFlow::Continue(self.0)
+    }
+    fn kind(&self) -> Option<u64> {
+        self.0
+    }
+}
+
+#[test]
+fn unhandled_kind_is_rejected_when_sent() {
+    let pool = Pool::<Job>::new(2)
+        .runner(0, RunnerSpec::new().kinds([1]))
+        .runner(1, RunnerSpec::new().kinds([1]))
+        .start();
+    let rejected = pool.send(Job(Some(2), Duration::ZERO)).unwrap_err();
+    assert!(matches!(
+        rejected,
+        PoolSendError::Unhandled(Job(Some(2), _))
+    ));
+    pool.send(Job(Some(1), Duration::ZERO)).unwrap();
+    assert_eq!(pool.recv().unwrap(), Some(1));
+}
+
+#[test]
+fn stopped_pool_rejects_as_stopped() {
+    let pool = Pool::<Job>::new(1).start();
+    let client = pool.client(Share::default());
+    drop(pool.stop_and_close().unwrap());
+    let rejected = client.send(Job(Some(2), Duration::ZERO)).unwrap_err();
+    assert!(matches!(rejected, PoolSendError::Stopped(Job(Some(2), _))));
+}
+
+#[test]
+fn shrinking_keeps_the_only_runner_of_a_kind() {
+    let pool = Pool::<Job>::new(3)
+        .runner(0, RunnerSpec::new().kinds([1]))
+        .runner(1, RunnerSpec::new().kinds([1]))
+        .runner(2, RunnerSpec::new().kinds([7]))
+        .start();
+    pool.resize(1);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(pool.stats().runners, 3);
+    pool.send(Job(Some(7), Duration::ZERO)).unwrap();
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(pool.recv().unwrap());
+    });
+    assert_eq!(recv.recv_timeout(Duration::from_secs(5)), Ok(Some(7)));
+}
+
+#[test]
+fn idle_runner_with_more_weight_is_preferred() {
+    let pool = Pool::<Job>::new(2)
+        .runner(0, RunnerSpec::new().weight(1))
+        .runner(1, RunnerSpec::new().weight(4))
+        .start();
+    pool.send(Job(None, Duration::from_millis(100))).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(pool.stats().in_flight, [0, 1]);
+    pool.recv().unwrap();
+}
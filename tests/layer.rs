@@ -0,0 +1,93 @@
+use a_run::layer::{Layers, Next};
+use a_run::pool::Pool;
+use a_run::queue::RunnerApi;
+use a_run::runner::{Batching, ControlExecuteMessage, RunnerApi as _};
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+/// Request logging that it executed, returning it's number
+struct Job(u32, Arc<Mutex<Vec<String>>>);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        self.1.lock().unwrap().push(format!("execute {}", self.0));
+        ControlFlow::Continue(self.0)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+/// Layer logging around the layers inside it and adding `add` to the request's number
+fn logging(name: &'static str, add: u32) -> impl Fn(Job, Next<'_, Job>) -> ControlFlow<(), u32> {
+    move |job: Job, next: Next<'_, Job>| {
+        let log = job.1.clone();
+        log.lock().unwrap().push(format!("{name} in"));
+        let res = next(Job(job.0 + add, job.1));
+        log.lock().unwrap().push(format!("{name} out"));
+        res
+    }
+}
+
+fn layers() -> Layers<Job> {
+    Layers::new()
+        .layer(logging("outer", 10))
+        .layer(logging("inner", 100))
+}
+
+const ORDER: [&str; 5] = [
+    "outer in",
+    "inner in",
+    "execute 111",
+    "inner out",
+    "outer out",
+];
+
+#[test]
+fn runners_call_the_first_layer_outermost() {
+    let (res, log) = within(|| {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let runner = RunnerApi::<Job>::new().layers(layers());
+        runner.send(Job(1, log.clone())).unwrap();
+        let res = runner.recv().unwrap();
+        (res, log.lock().unwrap().clone())
+    });
+    assert_eq!(res, 111);
+    assert_eq!(log, ORDER);
+}
+
+#[test]
+fn pools_call_the_first_layer_outermost() {
+    let (res, log) = within(|| {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let pool = Pool::<Job>::new(2).layers(layers()).start();
+        pool.send(Job(1, log.clone())).unwrap();
+        let res = pool.recv().unwrap();
+        (res, log.lock().unwrap().clone())
+    });
+    assert_eq!(res, 111);
+    assert_eq!(log, ORDER);
+}
+
+#[test]
+#[should_panic(expected = "batching runners can't execute requests through layers")]
+fn batching_pools_refuse_layers() {
+    let _ = Pool::<Job>::new(1)
+        .batching(Batching::new(4, Duration::ZERO))
+        .layers(layers());
+}
+
+#[test]
+#[should_panic(expected = "batching runners can't execute requests through layers")]
+fn batching_runners_refuse_layers() {
+    let _ = RunnerApi::<Job>::with_batching(Batching::new(4, Duration::ZERO)).layers(layers());
+}
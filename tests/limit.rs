@@ -0,0 +1,66 @@
+use a_run::pool::{Pool, Rate};
+use a_run::priority::Priority;
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// Request returning it's id, all of them sharing one rate limiting key
+struct Keyed(u32);
+
+impl ControlExecuteMessage for Keyed {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        ControlFlow::Continue(self.0)
+    }
+    fn key(&self) -> Option<u64> {
+        Some(7)
+    }
+}
+
+#[test]
+fn delayed_requests_keep_priority_order() {
+    let pool = Pool::<Keyed>::new(1)
+        .rate_limit_per_key(Rate::new(20.0, 1))
+        .start();
+    pool.send(Keyed(0)).unwrap();
+    assert_eq!(pool.recv().unwrap(), 0);
+    // Each one is delayed before the next is sent
+    for (id, priority) in [(1, Priority::LOW), (2, Priority::NORMAL), (3, Priority::HIGH)] {
+        pool.send_with_priority(Keyed(id), priority).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    pool.send_with_priority(Keyed(4), Priority::HIGH).unwrap();
+    let order: Vec<_> = (0..4).map(|_| pool.recv().unwrap()).collect();
+    assert_eq!(order, [3, 4, 2, 1]);
+}
+
+#[test]
+fn cancelled_delayed_requests_take_no_token() {
+    let pool = Pool::<Keyed>::new(1)
+        .rate_limit_per_key(Rate::new(10.0, 1))
+        .start();
+    pool.send(Keyed(0)).unwrap();
+    assert_eq!(pool.recv().unwrap(), 0);
+    let start = Instant::now();
+    let tickets: Vec<_> = (1..5).map(|id| pool.send(Keyed(id)).unwrap()).collect();
+    pool.send(Keyed(5)).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    for ticket in &tickets {
+        ticket.cancel();
+    }
+    // Only the one token the first request took has to be refilled
+    assert_eq!(pool.recv().unwrap(), 5);
+    assert!(start.elapsed() < Duration::from_millis(250), "{:?}", start.elapsed());
+}
+
+#[test]
+#[should_panic(expected = "positive")]
+fn zero_rate_is_rejected() {
+    let _ = Rate::new(0.0, 1);
+}
+
+#[test]
+#[should_panic(expected = "positive")]
+fn nan_rate_is_rejected() {
+    let _ = Rate::new(f64::NAN, 1);
+}
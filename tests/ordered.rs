@@ -0,0 +1,65 @@
+use a_run::pool::Pool;
+use a_run::runner::{ControlExecuteMessage, StopRunner};
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request returning it's number after sleeping longer for lower ones, `None` stops the runner
+struct Job(Option<u64>);
+
+impl ControlExecuteMessage for Job {
+    type Res = u64;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let Some(n) = self.0 else {
+            return ControlFlow::Break(());
+        };
+        std::thread::sleep(Duration::from_millis(10 - n % 10));
+        ControlFlow::Continue(n)
+    }
+}
+
+struct StopJob;
+
+impl StopRunner<Job> for StopJob {
+    fn get(&self) -> Job {
+        Job(None)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of releasing the responses")
+}
+
+#[test]
+fn responses_come_in_submission_order() {
+    let responses = within(|| {
+        let pool = Pool::<Job>::new(4).ordered(3).start();
+        for n in 0..30 {
+            pool.send(Job(Some(n))).unwrap();
+        }
+        (0..30).map(|_| pool.recv().unwrap()).collect::<Vec<_>>()
+    });
+    assert_eq!(responses, (0..30).collect::<Vec<_>>());
+}
+
+#[test]
+fn responses_held_back_are_released_in_order_when_stopped() {
+    let (received, rest) = within(|| {
+        let pool = Pool::<Job>::new(4).ordered(2).start();
+        for n in 0..20 {
+            pool.send(Job(Some(n))).unwrap();
+        }
+        let received: Vec<_> = (0..5).map(|_| pool.recv().unwrap()).collect();
+        let (closer, rest) = pool.stop().unwrap();
+        closer.close_await(&StopJob);
+        (received, rest.try_iter().collect::<Vec<_>>())
+    });
+    assert_eq!(received, (0..5).collect::<Vec<_>>());
+    assert_eq!(rest, (5..20).collect::<Vec<_>>());
+}
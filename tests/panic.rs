@@ -0,0 +1,128 @@
+use a_run::pool::Pool;
+use a_run::runner::{ControlExecuteMessage, StopRunner};
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request returning it's number, panicking on zero, `None` stops the runner
+struct Job(Option<u32>);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let Some(n) = self.0 else {
+            return ControlFlow::Break(());
+        };
+        assert_ne!(n, 0, "job panicked");
+        ControlFlow::Continue(n)
+    }
+}
+
+struct StopJob;
+
+impl StopRunner<Job> for StopJob {
+    fn get(&self) -> Job {
+        Job(None)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of delivering the panic")
+}
+
+#[test]
+fn map_resumes_a_panicking_request() {
+    let (panicked, rest) = within(|| {
+        let mut pool = Pool::<Job>::new(2).start();
+        let panicked = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.map([Job(Some(1)), Job(Some(0))]).collect::<Vec<_>>()
+        }))
+        .is_err();
+        let rest = pool.map([Job(Some(2)), Job(Some(3))]).collect::<Vec<_>>();
+        (panicked, rest)
+    });
+    assert!(panicked);
+    assert_eq!(rest, [2, 3]);
+}
+
+#[test]
+fn recv_resumes_a_panicking_request() {
+    let panicked = within(|| {
+        let pool = Pool::<Job>::new(1).start();
+        pool.send(Job(Some(0))).unwrap();
+        std::panic::catch_unwind(AssertUnwindSafe(|| pool.recv()))
+            .unwrap_err()
+            .downcast::<String>()
+            .is_ok_and(|msg| msg.contains("job panicked"))
+    });
+    assert!(panicked);
+}
+
+#[test]
+fn closing_resumes_a_panicking_request_once_stopped() {
+    let (panicked, responses) = within(|| {
+        let pool = Pool::<Job>::new(2).start();
+        pool.send(Job(Some(0))).unwrap();
+        pool.send(Job(Some(1))).unwrap();
+        let (closer, responses) = pool.stop().unwrap();
+        let panicked =
+            std::panic::catch_unwind(AssertUnwindSafe(|| closer.close_await(&StopJob))).is_err();
+        (panicked, responses.try_iter().collect::<Vec<_>>())
+    });
+    assert!(panicked);
+    assert_eq!(responses, [1]);
+}
+
+#[test]
+fn capturing_keeps_every_response_and_reports_panics() {
+    let captured = within(|| {
+        let pool = Pool::<Job>::new(2).start();
+        for n in [0, 1, 2] {
+            pool.send(Job(Some(n))).unwrap();
+        }
+        let closer = pool.stop_and_close().unwrap();
+        let mut responses = Vec::new();
+        let mut panics = 0;
+        for res in closer.close_capture(&StopJob) {
+            match res {
+                Ok(n) => responses.push(n),
+                Err(_) => panics += 1,
+            }
+        }
+        responses.sort();
+        (responses, panics)
+    });
+    assert_eq!(captured, (vec![1, 2], 1));
+}
+
+#[cfg(feature = "tower")]
+#[test]
+fn service_resolves_a_panicking_request() {
+    use a_run::pool::Share;
+    use a_run::service::ServiceError;
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+    use tower::Service;
+
+    let res = within(|| {
+        let pool = Pool::<Job>::new(1).start();
+        let mut service = pool.service(Share::default(), 1);
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(service.poll_ready(&mut cx).is_ready());
+        let mut call = std::pin::pin!(service.call(Job(Some(0))));
+        loop {
+            if let Poll::Ready(res) = call.as_mut().poll(&mut cx) {
+                return res;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    });
+    assert_eq!(res, Err(ServiceError::Panicked));
+}
@@ -0,0 +1,110 @@
+use a_run::cancel::Cancellation;
+use a_run::pool::Pool;
+use a_run::priority::Priority;
+use a_run::queue::RunnerApi;
+use a_run::runner::{ControlExecuteMessage, RunnerApi as _, StopRunner};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request returning it's id and counting the requests executed, stopping the runner without
+/// an id
+struct Job(Option<u32>, Arc<AtomicUsize>);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let Some(id) = self.0 else {
+            return ControlFlow::Break(());
+        };
+        self.1.fetch_add(1, Ordering::SeqCst);
+        ControlFlow::Continue(id)
+    }
+}
+
+struct StopJob(Arc<AtomicUsize>);
+
+impl StopRunner<Job> for StopJob {
+    fn get(&self) -> Job {
+        Job(None, self.0.clone())
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+#[test]
+fn paused_runners_hold_requests_until_resumed() {
+    let (executed, cancellation, responses) = within(|| {
+        let count = Arc::new(AtomicUsize::new(0));
+        let runner = RunnerApi::<Job>::new();
+        // Idle, so it's already waiting for a request
+        std::thread::sleep(Duration::from_millis(10));
+        runner.pause();
+        let job = |id| Job(Some(id), count.clone());
+        runner.send_with_priority(job(1), Priority::LOW).unwrap();
+        let cancelled = runner.send(job(2)).unwrap();
+        runner.send_with_priority(job(3), Priority::HIGH).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        let executed = count.load(Ordering::SeqCst);
+        let cancellation = cancelled.cancel();
+        runner.resume();
+        let responses: Vec<_> = (0..2).map(|_| runner.recv().unwrap()).collect();
+        (executed, cancellation, responses)
+    });
+    assert_eq!(executed, 0);
+    assert_eq!(cancellation, Cancellation::Dequeued);
+    assert_eq!(responses, [3, 1]);
+}
+
+#[test]
+fn paused_pools_queue_requests_until_resumed() {
+    let (executed, queued, responses) = within(|| {
+        let count = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::<Job>::new(2).start();
+        pool.pause();
+        for id in 0..3 {
+            pool.send(Job(Some(id), count.clone())).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        let executed = count.load(Ordering::SeqCst);
+        let queued = pool.stats().queued;
+        pool.resume();
+        let mut responses: Vec<_> = (0..3).map(|_| pool.recv().unwrap()).collect();
+        responses.sort();
+        (executed, queued, responses)
+    });
+    assert_eq!(executed, 0);
+    assert_eq!(queued, 3);
+    assert_eq!(responses, [0, 1, 2]);
+}
+
+#[test]
+fn closing_a_paused_pool_executes_it_s_queue() {
+    let responses = within(|| {
+        let count = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::<Job>::new(2).start();
+        pool.pause();
+        for id in 0..3 {
+            pool.send(Job(Some(id), count.clone())).unwrap();
+        }
+        let closer = pool.stop_and_close().unwrap();
+        let mut responses: Vec<_> = closer
+            .close_capture(&StopJob(count))
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        responses.sort();
+        responses
+    });
+    assert_eq!(responses, [0, 1, 2]);
+}
@@ -0,0 +1,48 @@
+use a_run::pipeline::{Pipeline, Stage};
+use a_run::priority::Priority;
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+/// Request passing it's number on after a while, odd ones with a high priority
+struct Step(u32);
+
+impl ControlExecuteMessage for Step {
+    type Res = Result<u32, ()>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(Duration::from_millis(1));
+        ControlFlow::Continue(Ok(self.0))
+    }
+    fn priority(&self) -> Priority {
+        if self.0 % 2 == 1 {
+            Priority::HIGH
+        } else {
+            Priority::LOW
+        }
+    }
+}
+
+fn keeps_order(runners: usize) {
+    let pipeline = Pipeline::<u32, u32, ()>::builder()
+        .stage(Stage::new(Step).runners(runners))
+        .start();
+    let sender = pipeline.sender();
+    let feeder = std::thread::spawn(move || {
+        for n in 0..60 {
+            sender.send(n).unwrap();
+        }
+    });
+    let out: Vec<_> = (0..60).map(|_| pipeline.recv().unwrap().unwrap()).collect();
+    feeder.join().unwrap();
+    assert_eq!(out, (0..60).collect::<Vec<_>>());
+}
+
+#[test]
+fn single_runner_stage_keeps_order_whatever_the_priority() {
+    keeps_order(1);
+}
+
+#[test]
+fn pooled_stage_keeps_order_whatever_the_priority() {
+    keeps_order(3);
+}
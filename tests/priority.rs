@@ -0,0 +1,79 @@
+use a_run::pool::Pool;
+use a_run::priority::{AGING, Lanes, Priority};
+use a_run::queue::RunnerApi;
+use a_run::runner::{ControlExecuteMessage, RunnerApi as _};
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request returning it's id after sleeping for a while
+struct Job(u32, Duration);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(self.1);
+        ControlFlow::Continue(self.0)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+/// Priorities sent while the first request executes, taken in reverse
+const PRIORITIES: [(u32, Priority); 3] = [
+    (1, Priority::LOW),
+    (2, Priority::NORMAL),
+    (3, Priority::HIGH),
+];
+
+#[test]
+fn runners_take_higher_priorities_first() {
+    let order = within(|| {
+        let runner = RunnerApi::<Job>::new();
+        runner.send(Job(0, Duration::from_millis(50))).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        for (id, priority) in PRIORITIES {
+            runner
+                .send_with_priority(Job(id, Duration::ZERO), priority)
+                .unwrap();
+        }
+        (0..4).map(|_| runner.recv().unwrap()).collect::<Vec<_>>()
+    });
+    assert_eq!(order, [0, 3, 2, 1]);
+}
+
+#[test]
+fn the_dispatcher_takes_higher_priorities_first() {
+    let order = within(|| {
+        let pool = Pool::<Job>::new(1).depth(1).start();
+        pool.send(Job(0, Duration::from_millis(50))).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        for (id, priority) in PRIORITIES {
+            pool.send_with_priority(Job(id, Duration::ZERO), priority)
+                .unwrap();
+        }
+        (0..4).map(|_| pool.recv().unwrap()).collect::<Vec<_>>()
+    });
+    assert_eq!(order, [0, 3, 2, 1]);
+}
+
+#[test]
+fn waiting_requests_age_past_higher_priorities() {
+    let mut lanes = Lanes::new();
+    lanes.push(Priority(100), "low");
+    let mut taken = Vec::new();
+    for _ in 0..2 * AGING {
+        lanes.push(Priority(101), "high");
+        taken.push(lanes.pop().unwrap());
+    }
+    // One level behind, it's taken on a tie once it waited for `AGING` requests
+    assert_eq!(taken.iter().position(|&v| v == "low"), Some(AGING));
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "prometheus")]
+
+use a_run::pool::{Exporter, Pool};
+use a_run::runner::ControlExecuteMessage;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request returning it's number
+struct Job(u32);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        ControlFlow::Continue(self.0)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of exporting the stats")
+}
+
+/// Exporter of a pool with 2 runners that executed 3 requests
+fn exporter() -> Exporter {
+    let pool = Pool::<Job>::new(2).start();
+    for n in 0..3 {
+        pool.send(Job(n)).unwrap();
+    }
+    for _ in 0..3 {
+        pool.recv().unwrap();
+    }
+    Exporter::new().pool("a\"b", pool.stats_handle())
+}
+
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn render_writes_the_text_exposition_format() {
+    let text = within(|| exporter().render());
+    let lines: Vec<_> = text.lines().collect();
+    for expected in [
+        "# TYPE a_run_runners gauge",
+        r#"a_run_runners{pool="a\"b"} 2"#,
+        r#"a_run_in_flight{pool="a\"b",runner="1"} 0"#,
+        "# TYPE a_run_completed_total counter",
+        r#"a_run_completed_total{pool="a\"b"} 3"#,
+        r#"a_run_failed_total{pool="a\"b"} 0"#,
+        "# TYPE a_run_queue_wait_seconds histogram",
+        r#"a_run_queue_wait_seconds_bucket{pool="a\"b",le="+Inf"} 3"#,
+        r#"a_run_queue_wait_seconds_count{pool="a\"b"} 3"#,
+    ] {
+        assert!(lines.contains(&expected), "{expected} missing from\n{text}");
+    }
+}
+
+#[test]
+fn serve_answers_metrics_requests_only() {
+    let (metrics, other) = within(|| {
+        let server = exporter().serve("127.0.0.1:0").unwrap();
+        let metrics = get(server.local_addr(), "/metrics");
+        let other = get(server.local_addr(), "/");
+        server.stop();
+        (metrics, other)
+    });
+    assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"), "{metrics}");
+    assert!(metrics.contains(r#"a_run_completed_total{pool="a\"b"} 3"#));
+    assert!(other.starts_with("HTTP/1.1 404 Not Found\r\n"), "{other}");
+}
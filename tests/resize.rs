@@ -0,0 +1,140 @@
+use a_run::pool::{Autoscale, Pool, PoolApi, Rate};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Request returning it's number after sleeping that many milliseconds, all of them under one
+/// rate limit key
+struct Job(u64);
+
+impl ControlExecuteMessage for Job {
+    type Res = u64;
+    fn key(&self) -> Option<u64> {
+        Some(0)
+    }
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(Duration::from_millis(self.0));
+        ControlFlow::Continue(self.0)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of resizing the pool")
+}
+
+/// Wait until the pool has `runners` runners taking requests, or fail after a while
+fn wait_for_runners(pool: &PoolApi<Job>, runners: usize) {
+    let start = Instant::now();
+    while pool.stats().runners != runners {
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "{} runners instead of {runners}",
+            pool.stats().runners
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Send `count` requests of `ms` milliseconds and receive their responses, returning the most
+/// runners the pool had meanwhile
+fn run(pool: &PoolApi<Job>, count: usize, ms: u64) -> usize {
+    for _ in 0..count {
+        pool.send(Job(ms)).unwrap();
+    }
+    let mut most = pool.stats().runners;
+    for _ in 0..count {
+        pool.recv().unwrap();
+        most = most.max(pool.stats().runners);
+    }
+    most
+}
+
+#[test]
+fn resizing_grows_and_shrinks_the_pool() {
+    within(|| {
+        let pool = Pool::<Job>::new(1).start();
+        pool.resize(3);
+        wait_for_runners(&pool, 3);
+        let start = Instant::now();
+        run(&pool, 3, 50);
+        assert!(
+            start.elapsed() < Duration::from_millis(140),
+            "{:?}",
+            start.elapsed()
+        );
+        pool.resize(0);
+        wait_for_runners(&pool, 1);
+    });
+}
+
+#[test]
+fn retiring_runners_finish_their_requests() {
+    let responses = within(|| {
+        let pool = Pool::<Job>::new(3).depth(2).start();
+        for ms in 1..=6 {
+            pool.send(Job(ms)).unwrap();
+        }
+        pool.resize(1);
+        let mut responses: Vec<_> = (0..6).map(|_| pool.recv().unwrap()).collect();
+        wait_for_runners(&pool, 1);
+        responses.sort();
+        responses
+    });
+    assert_eq!(responses, [1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn autoscaling_follows_the_load() {
+    within(|| {
+        let autoscale =
+            Autoscale::new(1, 4).cooldowns(Duration::from_millis(5), Duration::from_millis(50));
+        let pool = Pool::<Job>::new(1).depth(1).autoscale(autoscale).start();
+        assert!(run(&pool, 20, 10) > 1);
+        wait_for_runners(&pool, 1);
+    });
+}
+
+#[test]
+fn autoscaling_waits_for_requests_held_by_rate_limits() {
+    within(|| {
+        let autoscale =
+            Autoscale::new(1, 4).cooldowns(Duration::from_millis(5), Duration::from_millis(20));
+        let pool = Pool::<Job>::new(3)
+            .rate_limit_per_key(Rate::new(2.0, 1))
+            .autoscale(autoscale)
+            .start();
+        pool.send(Job(0)).unwrap();
+        pool.send(Job(0)).unwrap();
+        pool.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(pool.stats().runners, 3);
+        pool.recv().unwrap();
+        wait_for_runners(&pool, 1);
+    });
+}
+
+#[test]
+#[should_panic(expected = "utilisation must be in [0, 1]")]
+fn utilisation_above_one_is_rejected() {
+    let _ = Autoscale::new(1, 4).scale_up_at(1.5);
+}
+
+#[test]
+#[should_panic(expected = "utilisation must be in [0, 1]")]
+fn nan_utilisation_is_rejected() {
+    let _ = Autoscale::new(1, 4).scale_down_at(f64::NAN);
+}
+
+#[test]
+#[should_panic(expected = "autoscale must scale down below the utilisation it scales up at")]
+fn scaling_down_at_or_above_scaling_up_is_rejected() {
+    let autoscale = Autoscale::new(1, 4).scale_up_at(0.5).scale_down_at(0.5);
+    let _ = Pool::<Job>::new(1).autoscale(autoscale);
+}
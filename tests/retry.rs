@@ -0,0 +1,42 @@
+use a_run::pool::Pool;
+use a_run::retry::{MAX_BACKOFF, Retry, RetryPolicy};
+use a_run::runner::{ControlExecuteMessage, Ret};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Request that always fails
+#[derive(Clone)]
+struct Flaky;
+
+impl ControlExecuteMessage for Flaky {
+    type Res = Result<(), ()>;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        ControlFlow::Continue(Err(()))
+    }
+}
+
+#[test]
+fn cancelling_stops_the_backoff() {
+    let policy: Arc<RetryPolicy<Ret<Flaky>>> = Arc::new(
+        RetryPolicy::new(10, Result::is_err).backoff(Duration::from_secs(4), Duration::from_secs(4)),
+    );
+    let pool = Pool::<Retry<Flaky>>::new(1).start();
+    let ticket = pool.send(Retry::new(Flaky, &policy)).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    let cancelled = Instant::now();
+    ticket.cancel();
+    let retried = pool.recv().unwrap();
+    assert!(cancelled.elapsed() < Duration::from_millis(500));
+    assert!(retried.attempts < 10);
+    assert_eq!(retried.result, Err(()));
+}
+
+#[test]
+fn backoff_is_capped() {
+    let policy: RetryPolicy<Ret<Flaky>> =
+        RetryPolicy::new(3, Result::is_err).backoff(Duration::from_secs(60), Duration::from_secs(600));
+    let debug = format!("{policy:?}");
+    assert!(debug.contains(&format!("max_delay: {MAX_BACKOFF:?}")), "{debug}");
+    assert!(debug.contains(&format!("base: {MAX_BACKOFF:?}")), "{debug}");
+}
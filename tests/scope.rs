@@ -0,0 +1,137 @@
+use a_run::pool::{Pool, Watchdog};
+use a_run::runner::{ControlExecuteMessage, StopRunner};
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Borrowed counters of the requests executing and executed
+#[derive(Default)]
+struct Counts {
+    running: AtomicUsize,
+    done: AtomicUsize,
+}
+
+/// Request borrowing it's counters for a while, `None` stops the runner
+struct Job<'a>(Option<&'a Counts>);
+
+impl ControlExecuteMessage for Job<'_> {
+    type Res = ();
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        let Some(counts) = self.0 else {
+            return ControlFlow::Break(());
+        };
+        counts.running.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        counts.done.fetch_add(1, Ordering::SeqCst);
+        counts.running.fetch_sub(1, Ordering::SeqCst);
+        ControlFlow::Continue(())
+    }
+}
+
+struct StopJob;
+
+impl<'a> StopRunner<Job<'a>> for StopJob {
+    fn get(&self) -> Job<'a> {
+        Job(None)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of closing the scope")
+}
+
+#[test]
+fn borrowed_data_outlives_every_runner() {
+    let counts = Counts::default();
+    Pool::<Job>::new(3).scope(&StopJob, |api| {
+        for _ in 0..6 {
+            api.send(Job(Some(&counts))).unwrap();
+        }
+    });
+    assert_eq!(counts.running.load(Ordering::SeqCst), 0);
+    let done = counts.done.load(Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(counts.done.load(Ordering::SeqCst), done);
+}
+
+#[test]
+fn a_panic_in_f_still_joins_every_runner() {
+    let counts = Counts::default();
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        Pool::<Job>::new(3).scope(&StopJob, |api| {
+            for _ in 0..6 {
+                api.send(Job(Some(&counts))).unwrap();
+            }
+            panic!("scope panicked");
+        })
+    }));
+    let msg = res.unwrap_err().downcast::<&str>().unwrap();
+    assert_eq!(*msg, "scope panicked");
+    assert_eq!(counts.running.load(Ordering::SeqCst), 0);
+    let done = counts.done.load(Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(counts.done.load(Ordering::SeqCst), done);
+}
+
+#[test]
+fn unreceived_ordered_responses_dont_hang_the_scope() {
+    let running = within(|| {
+        let counts = Counts::default();
+        Pool::<Job>::new(2).ordered(2).scope(&StopJob, |api| {
+            for _ in 0..8 {
+                api.send(Job(Some(&counts))).unwrap();
+            }
+        });
+        counts.running.load(Ordering::SeqCst)
+    });
+    assert_eq!(running, 0);
+}
+
+/// Request panicking when executed, `false` stops the runner
+struct Panics(bool);
+
+impl ControlExecuteMessage for Panics {
+    type Res = ();
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        assert!(!self.0, "request panicked");
+        ControlFlow::Break(())
+    }
+}
+
+struct StopPanics;
+
+impl StopRunner<Panics> for StopPanics {
+    fn get(&self) -> Panics {
+        Panics(false)
+    }
+}
+
+#[test]
+fn an_unreceived_panic_is_resumed_after_closing() {
+    let panicked = within(|| {
+        std::panic::catch_unwind(|| {
+            Pool::<Panics>::new(1).scope(&StopPanics, |api| {
+                api.send(Panics(true)).unwrap();
+            })
+        })
+        .is_err()
+    });
+    assert!(panicked);
+}
+
+#[test]
+#[should_panic(expected = "a scoped pool can't replace stuck runners")]
+fn replacing_stuck_runners_is_refused() {
+    let watchdog = Watchdog::new(Duration::from_millis(10)).replace(true);
+    Pool::<Job>::new(1)
+        .watchdog(watchdog)
+        .scope(&StopJob, |_| ());
+}
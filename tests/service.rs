@@ -0,0 +1,64 @@
+#![cfg(feature = "tower")]
+
+use a_run::pool::{Pool, PoolService, Share};
+use a_run::runner::ControlExecuteMessage;
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+use tower::Service;
+
+/// Request sleeping for a while, counting the ones executed
+struct Job(Duration, Arc<AtomicUsize>);
+
+impl ControlExecuteMessage for Job {
+    type Res = ();
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(self.0);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Poll `f` until it's ready, failing instead of hanging when it takes too long
+fn wait<F: Future>(f: F) -> F::Output {
+    let mut f = std::pin::pin!(f);
+    let mut cx = Context::from_waker(Waker::noop());
+    let start = Instant::now();
+    loop {
+        if let Poll::Ready(res) = f.as_mut().poll(&mut cx) {
+            return res;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "never resolved");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn dropping_a_future_withdraws_its_request() {
+    let executed = Arc::new(AtomicUsize::new(0));
+    let pool = Pool::<Job>::new(1).depth(1).start();
+    let mut service = pool.service(Share::default(), 2);
+    let call = |service: &mut PoolService<Job>, dur| {
+        wait(std::future::poll_fn(|cx| {
+            Service::<Job>::poll_ready(service, cx)
+        }))
+        .unwrap();
+        service.call(Job(dur, executed.clone()))
+    };
+    let running = call(&mut service, Duration::from_millis(50));
+    drop(call(&mut service, Duration::ZERO));
+    assert_eq!(wait(running), Ok(()));
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(executed.load(Ordering::SeqCst), 1);
+    // Both places are free again
+    let again = [
+        call(&mut service, Duration::ZERO),
+        call(&mut service, Duration::ZERO),
+    ];
+    for res in again {
+        assert_eq!(wait(res), Ok(()));
+    }
+}
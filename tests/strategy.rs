@@ -0,0 +1,41 @@
+use a_run::pool::{Pool, Strategy};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+
+struct Job;
+
+impl ControlExecuteMessage for Job {
+    type Res = ();
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        ControlFlow::Continue(())
+    }
+}
+
+fn latency(alpha: f64) -> Pool<Job> {
+    Pool::new(1).strategy(Strategy::Latency { alpha })
+}
+
+#[test]
+fn alpha_of_one_is_accepted() {
+    let pool = latency(1.0).start();
+    pool.send(Job).unwrap();
+    pool.recv().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "alpha")]
+fn zero_alpha_is_rejected() {
+    let _ = latency(0.0);
+}
+
+#[test]
+#[should_panic(expected = "alpha")]
+fn alpha_above_one_is_rejected() {
+    let _ = latency(1.5);
+}
+
+#[test]
+#[should_panic(expected = "alpha")]
+fn nan_alpha_is_rejected() {
+    let _ = latency(f64::NAN);
+}
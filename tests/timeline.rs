@@ -0,0 +1,90 @@
+use a_run::pool::Pool;
+use a_run::runner::ControlExecuteMessage;
+use a_run::timeline::Recorder;
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Request returning it's number after sleeping that many milliseconds
+struct Job(u64);
+
+impl ControlExecuteMessage for Job {
+    type Res = u64;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(Duration::from_millis(self.0));
+        ControlFlow::Continue(self.0)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+/// Record 3 requests executed by a pool of one runner, sent from a thread named `sender`
+fn record(recorder: &Recorder, sender: &str) {
+    let (recorder, sender) = (recorder.clone(), sender.to_owned());
+    within(move || {
+        let send = move || {
+            let pool = Pool::<Job>::new(1).record(&recorder).start();
+            for ms in 1..=3 {
+                pool.send(Job(ms)).unwrap();
+            }
+            for _ in 0..3 {
+                pool.recv().unwrap();
+            }
+        };
+        let sender = std::thread::Builder::new().name(sender).spawn(send);
+        sender.unwrap().join().unwrap();
+    });
+}
+
+#[test]
+fn requests_are_slices_on_their_runner_linked_to_their_sender() {
+    let recorder = Recorder::new();
+    record(&recorder, "sender");
+    let trace = recorder.chrome_trace();
+    assert!(
+        trace.starts_with(r#"{"displayTimeUnit":"ms","traceEvents":[{"name":"process_name""#),
+        "{trace}"
+    );
+    assert!(trace.ends_with("}]}"), "{trace}");
+    let count = |pattern: &str| trace.matches(pattern).count();
+    assert_eq!(count(r#""args":{"name":"runner 0"}"#), 1, "{trace}");
+    assert_eq!(count(r#""args":{"name":"runner 1"}"#), 0, "{trace}");
+    assert_eq!(count(r#""args":{"name":"sender"}"#), 1, "{trace}");
+    for slice in ["enqueue", "dispatch", "execute"] {
+        assert_eq!(
+            count(&format!(r#""name":"{slice}","cat":"request","ph":"X""#)),
+            3
+        );
+    }
+    // One flow per request, from it's enqueue to it's execution
+    assert_eq!(count(r#""ph":"s""#), 3, "{trace}");
+    assert_eq!(count(r#""ph":"f""#), 3, "{trace}");
+    assert_eq!(count(r#""bp":"e""#), 3, "{trace}");
+}
+
+#[test]
+fn thread_names_are_escaped() {
+    let recorder = Recorder::new();
+    record(&recorder, "a \"quoted\\name\"");
+    let trace = recorder.chrome_trace();
+    assert!(
+        trace.contains(r#""args":{"name":"a \"quoted\\name\""}"#),
+        "{trace}"
+    );
+}
+
+#[test]
+fn clearing_forgets_the_requests() {
+    let recorder = Recorder::new();
+    record(&recorder, "sender");
+    recorder.clear();
+    assert!(!recorder.chrome_trace().contains(r#""cat":"request""#));
+}
@@ -0,0 +1,167 @@
+#![cfg(feature = "tracing")]
+
+use a_run::pool::Pool;
+use a_run::runner::ControlExecuteMessage;
+use std::cell::RefCell;
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Request logging it's number, panicking on zero
+struct Job(u32);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        tracing::info!(job = self.0, "inside job");
+        assert_ne!(self.0, 0, "job panicked");
+        ControlFlow::Continue(self.0)
+    }
+}
+
+/// Run `f` on another thread, failing instead of hanging when it doesn't finish in time
+fn within<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (send, recv) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = send.send(f());
+    });
+    recv.recv_timeout(Duration::from_secs(5))
+        .expect("hung instead of answering every request")
+}
+
+/// Spans by id minus one, with their fields and parent
+static SPANS: Mutex<Vec<(String, Option<u64>)>> = Mutex::new(Vec::new());
+/// Events with the path of spans they're in
+static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Writes the fields of a span or event after it's name
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0 += &format!(" {}={value:?}", field.name());
+    }
+}
+
+/// Subscriber recording every span and event in [`SPANS`] and [`EVENTS`]
+struct Log;
+
+impl Log {
+    fn path(mut span: Option<u64>) -> String {
+        let spans = SPANS.lock().unwrap();
+        let mut path = Vec::new();
+        while let Some(id) = span {
+            let (name, parent) = &spans[id as usize - 1];
+            path.push(name.clone());
+            span = *parent;
+        }
+        path.reverse();
+        path.join(" > ")
+    }
+    fn current() -> Option<u64> {
+        ENTERED.with(|entered| entered.borrow().last().copied())
+    }
+}
+
+impl Subscriber for Log {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let parent = match span.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if span.is_contextual() => Self::current(),
+            None => None,
+        };
+        let mut fields = Fields(span.metadata().name().to_owned());
+        span.record(&mut fields);
+        let mut spans = SPANS.lock().unwrap();
+        spans.push((fields.0, parent));
+        Id::from_u64(spans.len() as u64)
+    }
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, event: &Event<'_>) {
+        let parent = match event.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if event.is_contextual() => Self::current(),
+            None => None,
+        };
+        let mut fields = Fields(event.metadata().level().to_string());
+        event.record(&mut fields);
+        let line = format!("{} | {}", Self::path(parent), fields.0);
+        EVENTS.lock().unwrap().push(line);
+    }
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+    fn exit(&self, _: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().pop());
+    }
+}
+
+/// Send `job` from inside a `handler` span tagged with `test`, returning the events logged
+/// under that span
+fn events_of(test: &'static str, job: u32) -> Vec<String> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| tracing::subscriber::set_global_default(Log).unwrap());
+    within(move || {
+        let pool = Pool::<Job>::new(1).start();
+        tracing::info_span!("handler", test).in_scope(|| pool.send(Job(job)).unwrap());
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pool.recv()));
+    });
+    let handler = format!("handler test={test:?}");
+    EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|event| event.starts_with(&handler))
+        .map(|event| event[handler.len()..].to_owned())
+        .collect()
+}
+
+#[test]
+fn requests_execute_in_a_span_under_the_sender() {
+    let events = events_of("nesting", 7);
+    let request = events
+        .iter()
+        .find(|event| event.contains("inside job"))
+        .expect("no event of the job");
+    assert!(
+        request.starts_with(" > request seq=")
+            && request.ends_with("| INFO message=inside job job=7"),
+        "{request}"
+    );
+    for step in [
+        "enqueued",
+        "dispatched",
+        "execution started",
+        "execution finished",
+    ] {
+        assert!(
+            events
+                .iter()
+                .any(|event| event.contains(&format!("message={step}"))),
+            "{step} missing from {events:#?}"
+        );
+    }
+}
+
+#[test]
+fn panicking_requests_log_a_warning() {
+    let events = events_of("panicking", 0);
+    assert!(
+        events
+            .iter()
+            .any(|event| event.contains("| WARN message=execution panicked")),
+        "{events:#?}"
+    );
+}
@@ -0,0 +1,40 @@
+use a_run::pool::{Pool, Watchdog};
+use a_run::runner::ControlExecuteMessage;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// Request sleeping for a while before returning it's number
+struct Job(u32, Duration);
+
+impl ControlExecuteMessage for Job {
+    type Res = u32;
+    fn execute(self) -> ControlFlow<(), Self::Res> {
+        std::thread::sleep(self.1);
+        ControlFlow::Continue(self.0)
+    }
+}
+
+#[test]
+fn ordered_pool_skips_requests_of_replaced_runners() {
+    let watchdog = Watchdog::new(Duration::from_millis(30))
+        .interval(Duration::from_millis(5))
+        .replace(true);
+    let pool = Pool::<Job>::new(2)
+        .depth(1)
+        .ordered(4)
+        .watchdog(watchdog)
+        .start();
+    let start = Instant::now();
+    pool.send(Job(0, Duration::from_millis(500))).unwrap();
+    for n in 1..=8 {
+        pool.send(Job(n, Duration::ZERO)).unwrap();
+    }
+    let quick: Vec<_> = (0..8).map(|_| pool.recv().unwrap()).collect();
+    assert!(
+        start.elapsed() < Duration::from_millis(400),
+        "{:?}",
+        start.elapsed()
+    );
+    assert_eq!(quick, (1..=8).collect::<Vec<_>>());
+    assert_eq!(pool.recv().unwrap(), 0);
+}